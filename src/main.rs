@@ -1,6 +1,16 @@
+use std::f32::consts::PI;
 use std::fs;
+use std::io::Write;
+use std::iter::Enumerate;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 use std::path::Path;
+use std::slice::{ChunksExact, ChunksExactMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod noise;
 
 // MAIN
 
@@ -198,6 +208,52 @@ impl Color {
 
         format!("{} {} {}", red, green, blue)
     }
+
+    // Perceived brightness using the Rec. 709 weights.
+    fn luminance(&color: &Color) -> f32 {
+        0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue
+    }
+
+    // Radiance RGBE shares one exponent between the three mantissas, so the
+    // brightest channel keeps 8 bits of precision at any magnitude.
+    fn to_rgbe(&color: &Color) -> [u8; 4] {
+        let red: f32 = color.red.max(0.0);
+        let green: f32 = color.green.max(0.0);
+        let blue: f32 = color.blue.max(0.0);
+        let brightest: f32 = red.max(green).max(blue);
+
+        if brightest < 1e-32 {
+            return [0, 0, 0, 0];
+        }
+
+        // Equivalent to frexp: brightest = mantissa * 2^exponent with the
+        // mantissa in [0.5, 1). Values this large are always normal floats.
+        // The exponent byte tops out at 2^127, so anything brighter saturates
+        // to the largest mantissas instead of wrapping to black.
+        let exponent: i32 = (((brightest.to_bits() >> 23) & 0xff) as i32 - 126).min(127);
+        let scale: f32 = 256.0 / 2.0_f32.powi(exponent);
+
+        [
+            (red * scale) as u8,
+            (green * scale) as u8,
+            (blue * scale) as u8,
+            (exponent + 128) as u8,
+        ]
+    }
+
+    fn from_rgbe(rgbe: [u8; 4]) -> Color {
+        if rgbe[3] == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let scale: f32 = 2.0_f32.powi(rgbe[3] as i32 - (128 + 8));
+
+        Color::new(
+            (rgbe[0] as f32 + 0.5) * scale,
+            (rgbe[1] as f32 + 0.5) * scale,
+            (rgbe[2] as f32 + 0.5) * scale,
+        )
+    }
 }
 
 impl Add for Color {
@@ -250,67 +306,105 @@ impl Mul<Color> for Color {
 
 // CANVAS
 
+// Pixels are stored row by row in a single buffer, so pixel (x, y) lives at
+// index y * width + x.
+#[derive(Clone, Debug)]
 struct Canvas {
     width: usize,
     height: usize,
-    pixels: Vec<Vec<Color>>,
+    pixels: Vec<Color>,
 }
 
 impl Canvas {
     fn new(width: usize, height: usize) -> Self {
         let color: Color = Color::new(0.0, 0.0, 0.0);
-        let pixels: Vec<Vec<Color>> = vec![vec![color; width]; height];
+        let size: usize = width
+            .checked_mul(height)
+            .expect("Canvas width * height overflows usize");
+        let pixels: Vec<Color> = vec![color; size];
 
         Canvas {
             width,
             height,
-            pixels: pixels,
+            pixels,
         }
     }
 
-    fn pixel_at(canvas: Self, x: usize, y: usize) -> Color {
-        let pixels: Vec<Vec<Color>> = canvas.pixels;
-
-        pixels[y][x]
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
     }
 
-    fn write_pixel(canvas: Self, x: usize, y: usize, color: Color) -> Canvas {
-        let mut pixels: Vec<Vec<Color>> = canvas.pixels;
+    fn pixel_at(&self, x: usize, y: usize) -> Option<Color> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
 
+    fn write_pixel(&mut self, x: usize, y: usize, color: Color) {
         // Only writes pixels that exist in the canvas.
-        match pixels.get(y) {
-            Some(colors) => match colors.get(x) {
-                Some(_color) => pixels[y][x] = color,
-                None => (),
-            },
-            None => (),
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = color;
         }
+    }
 
-        Canvas {
-            width: canvas.width,
-            height: canvas.height,
-            pixels: pixels,
-        }
+    fn row(&self, y: usize) -> Option<&[Color]> {
+        self.rows().nth(y)
+    }
+
+    fn row_mut(&mut self, y: usize) -> Option<&mut [Color]> {
+        self.rows_mut().nth(y)
+    }
+
+    // A zero width canvas has no pixels, so it yields no rows at all even
+    // when its height is not zero. Callers counting rows against height must
+    // allow for that.
+    fn rows(&self) -> ChunksExact<'_, Color> {
+        self.pixels.chunks_exact(self.width.max(1))
+    }
+
+    // Rows are disjoint slices, so they can be handed to separate threads.
+    fn rows_mut(&mut self) -> ChunksExactMut<'_, Color> {
+        self.pixels.chunks_exact_mut(self.width.max(1))
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (usize, usize, Color)> + '_ {
+        let width: usize = self.width;
+
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(index, &color)| (index % width, index / width, color))
+    }
+
+    fn pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Color)> + '_ {
+        let width: usize = self.width;
+
+        self.pixels
+            .iter_mut()
+            .enumerate()
+            .map(move |(index, color)| (index % width, index / width, color))
     }
 
-    fn canvas_to_ppm(canvas: Self) -> String {
+    fn canvas_to_ppm(&self) -> String {
         let ppm_magic_number: String = "P3".to_string();
         let maximum_color_value: i32 = 255;
-        let pixels: String = Self::pixels_to_string(canvas.pixels);
+        let pixels: String = self.pixels_to_string();
 
         format!(
             "{}\n{} {}\n{}\n{}\n",
-            ppm_magic_number, canvas.width, canvas.height, maximum_color_value, pixels
+            ppm_magic_number, self.width, self.height, maximum_color_value, pixels
         )
     }
 
-    fn pixels_to_string(pixels: Vec<Vec<Color>>) -> String {
+    fn pixels_to_string(&self) -> String {
         let mut rows: Vec<String> = vec![];
 
-        for row in pixels.iter() {
+        for row in self.rows() {
             let mut colors: Vec<String> = vec![];
             for color in row {
-                colors.push(Color::to_string(&color));
+                colors.push(Color::to_string(color));
             }
             rows.push(colors.join(" "));
         }
@@ -322,551 +416,3868 @@ impl Canvas {
     fn write_ppm_to_file(ppm: String) {
         fs::write("./assets/canvas.ppm", ppm).expect("Unable to write file");
     }
-}
-
-// MATRICES
 
-#[derive(Clone, Debug)]
-struct Matrix {
-    size: usize,
-    values: Vec<Vec<i32>>,
-}
+    fn from_ppm(ppm: &[u8]) -> Result<Canvas, String> {
+        let mut reader: PpmReader = PpmReader::new(ppm);
+
+        let binary: bool = match reader.next_token()? {
+            "P3" => false,
+            "P6" => true,
+            magic_number => return Err(format!("Unsupported PPM format: {}", magic_number)),
+        };
+        let width: usize = reader.next_number()?;
+        let height: usize = reader.next_number()?;
+        let maximum_color_value: usize = reader.next_number()?;
+
+        if maximum_color_value == 0 || maximum_color_value > 65535 {
+            return Err(format!(
+                "Invalid maximum color value: {}",
+                maximum_color_value
+            ));
+        }
 
-impl Matrix {
-    fn new() -> Self {
-        const MATRIX_SIZE: usize = 4;
-        let initial_value: i32 = 0;
+        // Binary data starts after exactly one whitespace character.
+        if binary {
+            reader.skip_single_whitespace()?;
+        }
 
-        Matrix {
-            size: MATRIX_SIZE,
-            values: vec![vec![initial_value; MATRIX_SIZE]; MATRIX_SIZE],
+        // Plain samples take at least one digit each, binary samples one or
+        // two bytes.
+        let sample_size: usize = if binary && maximum_color_value > 255 {
+            2
+        } else {
+            1
+        };
+        reader.check_pixel_data(width, height, 3 * sample_size)?;
+
+        let mut canvas: Canvas = Canvas::new(width, height);
+        let scale: f32 = maximum_color_value as f32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut rgb: [f32; 3] = [0.0; 3];
+
+                for value in rgb.iter_mut() {
+                    let sample: usize = if binary {
+                        reader.next_binary_sample(maximum_color_value)?
+                    } else {
+                        reader.next_number()?
+                    };
+
+                    if sample > maximum_color_value {
+                        return Err(format!("Color value {} exceeds maximum", sample));
+                    }
+
+                    *value = sample as f32 / scale;
+                }
+
+                canvas.write_pixel(x, y, Color::new(rgb[0], rgb[1], rgb[2]));
+            }
         }
-    }
 
-    fn get(self: &Self, column: usize, row: usize) -> i32 {
-        self.values[column][row]
+        Ok(canvas)
     }
-}
 
-// TESTS
+    fn read_ppm_from_file(path: &Path) -> Result<Canvas, String> {
+        let ppm: Vec<u8> = fs::read(path).map_err(|error| error.to_string())?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        Self::from_ppm(&ppm)
+    }
 
-    #[test]
-    fn test_tuple_constructor_for_points() {
-        let tuple: Tuple = Tuple::new(4.3, -4.2, 3.1, 1.0);
-        assert_eq!(tuple.x, 4.3);
-        assert_eq!(tuple.y, -4.2);
-        assert_eq!(tuple.z, 3.1);
-        assert_eq!(tuple.w, 1.0);
+    fn canvas_to_png(&self, bit_depth: PngBitDepth) -> Vec<u8> {
+        let scanlines: Vec<u8> = self.pixels_to_png_scanlines(bit_depth);
 
-        let is_point: bool = Tuple::is_point(tuple);
-        assert!(is_point);
+        let mut header: Vec<u8> = vec![];
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.push(PngBitDepth::bits(bit_depth));
+        header.push(PNG_COLOR_TYPE_RGB);
+        header.push(0); // Compression method: deflate
+        header.push(0); // Filter method: adaptive
+        header.push(0); // Interlace method: none
 
-        let is_vector: bool = Tuple::is_vector(tuple);
-        assert!(!is_vector);
+        let mut png: Vec<u8> = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &header);
+        write_png_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines));
+        write_png_chunk(&mut png, b"IEND", &[]);
+
+        png
     }
 
-    #[test]
-    fn test_tuple_constructor_for_vectors() {
-        let tuple: Tuple = Tuple::new(4.3, -4.2, 3.1, 0.0);
-        assert_eq!(tuple.x, 4.3);
-        assert_eq!(tuple.y, -4.2);
-        assert_eq!(tuple.z, 3.1);
-        assert_eq!(tuple.w, 0.0);
+    fn pixels_to_png_scanlines(&self, bit_depth: PngBitDepth) -> Vec<u8> {
+        let bytes_per_pixel: usize = 3 * PngBitDepth::bytes(bit_depth);
+        let mut scanlines: Vec<u8> = vec![];
+        let mut previous_row: Vec<u8> = vec![];
 
-        let is_point: bool = Tuple::is_point(tuple);
-        assert!(!is_point);
+        for row in self.rows() {
+            let mut current_row: Vec<u8> = vec![];
+            for color in row {
+                for &value in [color.red, color.green, color.blue].iter() {
+                    match bit_depth {
+                        PngBitDepth::Eight => {
+                            current_row.push(Color::clamp_and_round(value) as u8);
+                        }
+                        PngBitDepth::Sixteen => {
+                            let sample: u16 = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                            current_row.extend_from_slice(&sample.to_be_bytes());
+                        }
+                    }
+                }
+            }
 
-        let is_vector: bool = Tuple::is_vector(tuple);
-        assert!(is_vector);
-    }
+            if previous_row.is_empty() {
+                previous_row = vec![0; current_row.len()];
+            }
 
-    #[test]
-    fn test_point_creates_a_point() {
-        let tuple: Tuple = Tuple::point(4.0, -4.0, 3.0);
-        assert_eq!(tuple.x, 4.0);
-        assert_eq!(tuple.y, -4.0);
-        assert_eq!(tuple.z, 3.0);
-        assert_eq!(tuple.w, 1.0);
+            scanlines.extend(filter_png_scanline(
+                &current_row,
+                &previous_row,
+                bytes_per_pixel,
+            ));
+            previous_row = current_row;
+        }
 
-        let is_point: bool = Tuple::is_point(tuple);
-        assert!(is_point);
+        scanlines
     }
 
-    #[test]
-    fn test_vector_creates_a_vector() {
-        let tuple: Tuple = Tuple::vector(4.0, -4.0, 3.0);
-        assert_eq!(tuple.x, 4.0);
-        assert_eq!(tuple.y, -4.0);
-        assert_eq!(tuple.z, 3.0);
-        assert_eq!(tuple.w, 0.00);
-
-        let is_vector: bool = Tuple::is_vector(tuple);
-        assert!(is_vector);
+    fn write_png_to_file(png: Vec<u8>) {
+        fs::write("./assets/canvas.png", png).expect("Unable to write file");
     }
 
-    #[test]
-    fn test_add_tuples() {
-        let tuple1: Tuple = Tuple::new(3.0, -2.0, 5.0, 1.0);
-        let tuple2: Tuple = Tuple::new(-2.0, 3.0, 1.0, 0.0);
-        let result: Tuple = tuple1 + tuple2;
+    // Portable Float Maps store rows from bottom to top. A negative scale
+    // marks little-endian data.
+    fn canvas_to_pfm(&self) -> Vec<u8> {
+        let mut pfm: Vec<u8> = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
 
-        assert_eq!(result.x, 1.0);
-        assert_eq!(result.y, 1.0);
-        assert_eq!(result.z, 6.0);
-        assert_eq!(result.w, 1.0);
+        for row in self.rows().rev() {
+            for color in row {
+                for &value in [color.red, color.green, color.blue].iter() {
+                    pfm.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        pfm
     }
 
-    #[test]
-    fn test_subtract_point_from_point() {
-        let point1: Tuple = Tuple::point(3.0, 2.0, 1.0);
-        let point2: Tuple = Tuple::point(5.0, 6.0, 7.0);
-        let result: Tuple = point1 - point2;
+    fn from_pfm(pfm: &[u8]) -> Result<Canvas, String> {
+        let mut reader: PpmReader = PpmReader::new(pfm);
 
-        assert_eq!(result.x, -2.0);
-        assert_eq!(result.y, -4.0);
-        assert_eq!(result.z, -6.0);
-        assert_eq!(result.w, 0.0);
-    }
+        let channels: usize = match reader.next_token()? {
+            "PF" => 3,
+            "Pf" => 1,
+            magic_number => return Err(format!("Unsupported PFM format: {}", magic_number)),
+        };
+        let width: usize = reader.next_number()?;
+        let height: usize = reader.next_number()?;
+        let scale: f32 = reader.next_float()?;
 
-    #[test]
-    fn test_subtract_vector_from_point() {
-        let point: Tuple = Tuple::point(3.0, 2.0, 1.0);
-        let vector: Tuple = Tuple::vector(5.0, 6.0, 7.0);
-        let result: Tuple = point - vector;
+        if scale == 0.0 || !scale.is_finite() {
+            return Err(format!("Invalid PFM scale: {}", scale));
+        }
 
-        assert_eq!(result.x, -2.0);
-        assert_eq!(result.y, -4.0);
-        assert_eq!(result.z, -6.0);
-        assert_eq!(result.w, 1.0);
-    }
+        reader.skip_single_whitespace()?;
+        reader.check_pixel_data(width, height, 4 * channels)?;
 
-    #[test]
-    fn test_subtract_vector_from_vector() {
-        let vector1: Tuple = Tuple::vector(3.0, 2.0, 1.0);
-        let vector2: Tuple = Tuple::vector(5.0, 6.0, 7.0);
-        let result: Tuple = vector1 - vector2;
+        let little_endian: bool = scale < 0.0;
+        let mut canvas: Canvas = Canvas::new(width, height);
 
-        assert_eq!(result.x, -2.0);
-        assert_eq!(result.y, -4.0);
-        assert_eq!(result.z, -6.0);
-        assert_eq!(result.w, 0.0);
-    }
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let mut rgb: [f32; 3] = [0.0; 3];
 
-    #[test]
-    fn test_subtract_vector_from_zero_vector() {
-        let vector1: Tuple = Tuple::vector(0.0, 0.0, 0.0);
-        let vector2: Tuple = Tuple::vector(1.0, -2.0, 3.0);
-        let result: Tuple = vector1 - vector2;
+                for value in rgb.iter_mut().take(channels) {
+                    let mut bytes: [u8; 4] = [0; 4];
+                    bytes.copy_from_slice(reader.next_bytes(4)?);
 
-        assert_eq!(result.x, -1.0);
-        assert_eq!(result.y, 2.0);
-        assert_eq!(result.z, -3.0);
-        assert_eq!(result.w, 0.0);
-    }
+                    *value = if little_endian {
+                        f32::from_le_bytes(bytes)
+                    } else {
+                        f32::from_be_bytes(bytes)
+                    } * scale.abs();
+                }
 
-    #[test]
-    fn test_negate_tuple() {
-        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
-        let result: Tuple = -tuple;
+                // Grayscale maps use the single channel for all three.
+                if channels == 1 {
+                    rgb = [rgb[0]; 3];
+                }
 
-        assert_eq!(result.x, -1.0);
-        assert_eq!(result.y, 2.0);
-        assert_eq!(result.z, -3.0);
-        assert_eq!(result.w, 4.0);
-    }
+                canvas.write_pixel(x, y, Color::new(rgb[0], rgb[1], rgb[2]));
+            }
+        }
 
-    #[test]
-    fn test_multiply_tuple_by_scalar() {
-        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
-        let result: Tuple = tuple * 3.5;
+        Ok(canvas)
+    }
 
-        assert_eq!(result.x, 3.5);
-        assert_eq!(result.y, -7.0);
-        assert_eq!(result.z, 10.5);
-        assert_eq!(result.w, -14.0);
+    fn write_pfm_to_file(pfm: Vec<u8>) {
+        fs::write("./assets/canvas.pfm", pfm).expect("Unable to write file");
     }
 
-    #[test]
-    fn test_divide_tuple_by_scalar() {
-        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
-        let result: Tuple = tuple / 2.0;
+    fn read_pfm_from_file(path: &Path) -> Result<Canvas, String> {
+        let pfm: Vec<u8> = fs::read(path).map_err(|error| error.to_string())?;
 
-        assert_eq!(result.x, 0.5);
-        assert_eq!(result.y, -1.0);
-        assert_eq!(result.z, 1.5);
-        assert_eq!(result.w, -2.0);
+        Self::from_pfm(&pfm)
     }
 
-    #[test]
-    fn test_vector_magnitude() {
-        let vector: Tuple = Tuple::vector(1.0, 0.0, 0.0);
-        let result: f32 = Tuple::magnitude(vector);
+    fn canvas_to_hdr(&self) -> Vec<u8> {
+        let mut hdr: Vec<u8> = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height, self.width
+        )
+        .into_bytes();
 
-        assert_eq!(result, 1.0);
-    }
+        for row in self.rows() {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(Color::to_rgbe).collect();
 
-    #[test]
-    fn test_vector_magnitude_pythagoras() {
-        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+            // Run length encoding is only defined for these widths.
+            if self.width < HDR_MIN_RLE_WIDTH || self.width > HDR_MAX_RLE_WIDTH {
+                for pixel in rgbe.iter() {
+                    hdr.extend_from_slice(pixel);
+                }
+                continue;
+            }
 
-        let expected: f32 = (14.0 as f32).sqrt();
-        let actual: f32 = Tuple::magnitude(vector);
+            hdr.extend_from_slice(&[2, 2, (self.width >> 8) as u8, self.width as u8]);
+            for channel in 0..4 {
+                let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[channel]).collect();
+                encode_hdr_channel(&values, &mut hdr);
+            }
+        }
 
-        assert_eq!(expected, actual);
+        hdr
     }
 
-    #[test]
-    fn test_normalize_simple_vector() {
-        let vector: Tuple = Tuple::vector(4.0, 0.0, 0.0);
-        let result: Tuple = Tuple::normalize(vector);
+    fn from_hdr(hdr: &[u8]) -> Result<Canvas, String> {
+        let mut lines = hdr.split(|&byte| byte == b'\n');
+        let mut position: usize = 0;
 
-        assert_eq!(result.x, 1.0);
-        assert_eq!(result.y, 0.0);
-        assert_eq!(result.z, 0.0);
-        assert_eq!(result.w, 0.0);
-    }
+        let mut next_line = || -> Result<String, String> {
+            let line: &[u8] = lines
+                .next()
+                .ok_or_else(|| "Unexpected end of HDR data".to_string())?;
+            position += line.len() + 1;
 
-    #[test]
-    fn test_normalize_vector() {
-        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
-        let result: Tuple = Tuple::normalize(vector);
+            Ok(String::from_utf8_lossy(line).trim_end().to_string())
+        };
 
-        assert_eq!(result.x, 1.0 / (14.0 as f32).sqrt());
-        assert_eq!(result.y, 2.0 / (14.0 as f32).sqrt());
-        assert_eq!(result.z, 3.0 / (14.0 as f32).sqrt());
-        assert_eq!(result.w, 0.0);
+        if !next_line()?.starts_with("#?") {
+            return Err("Missing Radiance HDR signature".to_string());
+        }
+
+        loop {
+            let line: String = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("Unsupported HDR format: {}", format));
+                }
+            }
+        }
+
+        let resolution: String = next_line()?;
+        let tokens: Vec<&str> = resolution.split_whitespace().collect();
+        let (height, width): (usize, usize) = match tokens.as_slice() {
+            ["-Y", height, "+X", width] => (
+                height
+                    .parse()
+                    .map_err(|_| format!("Invalid HDR height: {}", height))?,
+                width
+                    .parse()
+                    .map_err(|_| format!("Invalid HDR width: {}", width))?,
+            ),
+            _ => return Err(format!("Unsupported HDR resolution: {}", resolution)),
+        };
+
+        let mut data: &[u8] = hdr.get(position..).unwrap_or(&[]);
+        // Rejects headers claiming more scanlines than the data can hold,
+        // before a canvas of that size is allocated. Zero width scanlines
+        // take no bytes, so a zero dimension is only accepted when the other
+        // is zero too.
+        if (width == 0) != (height == 0) {
+            return Err(format!("Invalid HDR image size {}x{}", width, height));
+        }
+
+        let pixels: Option<usize> = width.checked_mul(height);
+        let required: Option<usize> = height.checked_mul(hdr_minimum_scanline_size(width));
+
+        if pixels.is_none() || !matches!(required, Some(required) if required <= data.len()) {
+            return Err(format!("Image size {}x{} exceeds HDR data", width, height));
+        }
+
+        let mut canvas: Canvas = Canvas::new(width, height);
+
+        for y in 0..height {
+            let rgbe: Vec<[u8; 4]> = decode_hdr_scanline(&mut data, width)?;
+
+            for (x, &pixel) in rgbe.iter().enumerate() {
+                canvas.write_pixel(x, y, Color::from_rgbe(pixel));
+            }
+        }
+
+        Ok(canvas)
     }
 
-    #[test]
-    fn test_magnitude_of_normalized_vector() {
-        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
-        let normalized_vector: Tuple = Tuple::normalize(vector);
-        let result: f32 = Tuple::magnitude(normalized_vector);
+    fn write_hdr_to_file(hdr: Vec<u8>) {
+        fs::write("./assets/canvas.hdr", hdr).expect("Unable to write file");
+    }
 
-        assert!(float_eq(result, 1.0));
+    fn read_hdr_from_file(path: &Path) -> Result<Canvas, String> {
+        let hdr: Vec<u8> = fs::read(path).map_err(|error| error.to_string())?;
+
+        Self::from_hdr(&hdr)
     }
+}
 
-    #[test]
-    fn test_dot_product() {
-        let vector1: Tuple = Tuple::vector(1.0, 2.0, 3.0);
-        let vector2: Tuple = Tuple::vector(2.0, 3.0, 4.0);
-        let result: f32 = Tuple::dot(vector1, vector2);
+struct PpmReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
 
-        assert_eq!(result, 20.0);
+impl<'a> PpmReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        PpmReader { bytes, position: 0 }
     }
 
-    #[test]
+    // Comments run from '#' to the end of the line and may appear anywhere
+    // whitespace is allowed in the header or in a plain (P3) file.
+    fn skip_whitespace_and_comments(&mut self) {
+        while let Some(&byte) = self.bytes.get(self.position) {
+            if byte == b'#' {
+                while let Some(&byte) = self.bytes.get(self.position) {
+                    if byte == b'\n' || byte == b'\r' {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<&'a str, String> {
+        self.skip_whitespace_and_comments();
+
+        let start: usize = self.position;
+        while let Some(&byte) = self.bytes.get(self.position) {
+            if byte.is_ascii_whitespace() || byte == b'#' {
+                break;
+            }
+            self.position += 1;
+        }
+
+        if start == self.position {
+            return Err("Unexpected end of PPM data".to_string());
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .map_err(|_| "Invalid characters in PPM data".to_string())
+    }
+
+    fn next_number(&mut self) -> Result<usize, String> {
+        let token: &str = self.next_token()?;
+
+        token
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid number in PPM data: {}", token))
+    }
+
+    fn skip_single_whitespace(&mut self) -> Result<(), String> {
+        match self.bytes.get(self.position) {
+            Some(byte) if byte.is_ascii_whitespace() => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err("Missing whitespace before PPM pixel data".to_string()),
+        }
+    }
+
+    // Samples are one byte when the maximum color value fits in a byte and
+    // two big-endian bytes otherwise.
+    fn next_binary_sample(&mut self, maximum_color_value: usize) -> Result<usize, String> {
+        let sample_size: usize = if maximum_color_value < 256 { 1 } else { 2 };
+        let end: usize = self.position + sample_size;
+
+        let bytes: &[u8] = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| "Unexpected end of PPM data".to_string())?;
+        self.position = end;
+
+        Ok(bytes
+            .iter()
+            .fold(0, |sample, &byte| (sample << 8) | byte as usize))
+    }
+
+    // Rejects headers claiming more pixels than the remaining data can hold,
+    // before a canvas of that size is allocated. A zero dimension needs no
+    // data at all, so it is only accepted when the other is zero too.
+    // Otherwise readers would loop over billions of empty rows.
+    fn check_pixel_data(
+        &self,
+        width: usize,
+        height: usize,
+        bytes_per_pixel: usize,
+    ) -> Result<(), String> {
+        if (width == 0) != (height == 0) {
+            return Err(format!("Invalid image size {}x{}", width, height));
+        }
+
+        let remaining: usize = self.bytes.len().saturating_sub(self.position);
+        let required: Option<usize> = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(bytes_per_pixel));
+
+        match required {
+            Some(required) if required <= remaining => Ok(()),
+            _ => Err(format!("Image size {}x{} exceeds PPM data", width, height)),
+        }
+    }
+
+    fn next_float(&mut self) -> Result<f32, String> {
+        let token: &str = self.next_token()?;
+
+        token
+            .parse::<f32>()
+            .map_err(|_| format!("Invalid number in PPM data: {}", token))
+    }
+
+    fn next_bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end: usize = self.position + count;
+
+        let bytes: &'a [u8] = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| "Unexpected end of PPM data".to_string())?;
+        self.position = end;
+
+        Ok(bytes)
+    }
+}
+
+// RADIANCE HDR
+
+const HDR_MIN_RLE_WIDTH: usize = 8;
+const HDR_MAX_RLE_WIDTH: usize = 0x7fff;
+const HDR_MIN_RUN: usize = 4;
+const HDR_MAX_RUN: usize = 127;
+const HDR_MAX_DUMP: usize = 128;
+
+// Each channel of a scanline is stored separately as a mix of runs (a count
+// above 128 followed by one value) and dumps (a count followed by raw values).
+fn encode_hdr_channel(values: &[u8], hdr: &mut Vec<u8>) {
+    let mut i: usize = 0;
+
+    while i < values.len() {
+        let mut run_start: usize = i;
+        let mut run_length: usize = 0;
+
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(HDR_MAX_RUN)
+                .take_while(|&&value| value == values[run_start])
+                .count();
+            if run_length >= HDR_MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        while i < run_start {
+            let count: usize = (run_start - i).min(HDR_MAX_DUMP);
+            hdr.push(count as u8);
+            hdr.extend_from_slice(&values[i..i + count]);
+            i += count;
+        }
+
+        if run_start < values.len() {
+            hdr.push((128 + run_length) as u8);
+            hdr.push(values[run_start]);
+            i = run_start + run_length;
+        }
+    }
+}
+
+// The fewest bytes a scanline of this width can be stored in: a run length
+// header plus one two-byte run per 127 pixels in each channel, or four bytes
+// per pixel when it is stored flat.
+fn hdr_minimum_scanline_size(width: usize) -> usize {
+    let flat: usize = width.saturating_mul(4);
+
+    if (HDR_MIN_RLE_WIDTH..=HDR_MAX_RLE_WIDTH).contains(&width) {
+        flat.min(4 + 4 * 2 * width.div_ceil(HDR_MAX_RUN))
+    } else {
+        flat
+    }
+}
+
+fn decode_hdr_scanline(data: &mut &[u8], width: usize) -> Result<Vec<[u8; 4]>, String> {
+    let is_run_length_encoded: bool = (HDR_MIN_RLE_WIDTH..=HDR_MAX_RLE_WIDTH).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && data[2] & 0x80 == 0;
+
+    if !is_run_length_encoded {
+        let bytes: &[u8] = take_hdr_bytes(data, width * 4)?;
+
+        return Ok(bytes
+            .chunks(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+            .collect());
+    }
+
+    let header: &[u8] = take_hdr_bytes(data, 4)?;
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return Err("HDR scanline width mismatch".to_string());
+    }
+
+    let mut rgbe: Vec<[u8; 4]> = vec![[0; 4]; width];
+
+    for channel in 0..4 {
+        let mut x: usize = 0;
+
+        while x < width {
+            let count: usize = take_hdr_bytes(data, 1)?[0] as usize;
+
+            if count > 128 {
+                let length: usize = count - 128;
+                let value: u8 = take_hdr_bytes(data, 1)?[0];
+                if x + length > width {
+                    return Err("HDR run exceeds scanline".to_string());
+                }
+                for pixel in rgbe[x..x + length].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += length;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err("Invalid HDR dump length".to_string());
+                }
+                for (pixel, &value) in rgbe[x..x + count]
+                    .iter_mut()
+                    .zip(take_hdr_bytes(data, count)?)
+                {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(rgbe)
+}
+
+fn take_hdr_bytes<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8], String> {
+    if data.len() < count {
+        return Err("Unexpected end of HDR data".to_string());
+    }
+    let (bytes, rest): (&'a [u8], &'a [u8]) = data.split_at(count);
+    *data = rest;
+
+    Ok(bytes)
+}
+
+// PNG
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const PNG_COLOR_TYPE_RGB: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PngBitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl PngBitDepth {
+    fn bits(bit_depth: PngBitDepth) -> u8 {
+        match bit_depth {
+            PngBitDepth::Eight => 8,
+            PngBitDepth::Sixteen => 16,
+        }
+    }
+
+    fn bytes(bit_depth: PngBitDepth) -> usize {
+        match bit_depth {
+            PngBitDepth::Eight => 1,
+            PngBitDepth::Sixteen => 2,
+        }
+    }
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start: usize = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+
+    // The checksum covers the chunk type and data but not the length.
+    let checksum: u32 = crc32(&png[start..]);
+    png.extend_from_slice(&checksum.to_be_bytes());
+}
+
+// Picks the filter type with the smallest sum of absolute differences, which
+// is the heuristic recommended by the PNG specification.
+fn filter_png_scanline(row: &[u8], previous_row: &[u8], bytes_per_pixel: usize) -> Vec<u8> {
+    let mut best_scanline: Vec<u8> = vec![];
+    let mut best_score: u64 = u64::MAX;
+
+    for filter_type in 0..5u8 {
+        let mut scanline: Vec<u8> = Vec::with_capacity(row.len() + 1);
+        scanline.push(filter_type);
+
+        for (i, &value) in row.iter().enumerate() {
+            let left: u8 = if i >= bytes_per_pixel {
+                row[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up: u8 = previous_row[i];
+            let up_left: u8 = if i >= bytes_per_pixel {
+                previous_row[i - bytes_per_pixel]
+            } else {
+                0
+            };
+
+            let prediction: u8 = match filter_type {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                _ => paeth_predictor(left, up, up_left),
+            };
+            scanline.push(value.wrapping_sub(prediction));
+        }
+
+        let score: u64 = scanline[1..]
+            .iter()
+            .map(|&byte| (byte as i8).unsigned_abs() as u64)
+            .sum();
+
+        if score < best_score {
+            best_score = score;
+            best_scanline = scanline;
+        }
+    }
+
+    best_scanline
+}
+
+fn paeth_predictor(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate: i16 = left as i16 + up as i16 - up_left as i16;
+    let left_distance: i16 = (estimate - left as i16).abs();
+    let up_distance: i16 = (estimate - up as i16).abs();
+    let up_left_distance: i16 = (estimate - up_left as i16).abs();
+
+    if left_distance <= up_distance && left_distance <= up_left_distance {
+        left
+    } else if up_distance <= up_left_distance {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask: u32 = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // Summing up to 5552 bytes before reducing cannot overflow a u32.
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+
+    (b << 16) | a
+}
+
+// DEFLATE
+
+const DEFLATE_WINDOW_SIZE: usize = 32768;
+const DEFLATE_MIN_MATCH: usize = 3;
+const DEFLATE_MAX_MATCH: usize = 258;
+const DEFLATE_MAX_CHAIN: usize = 64;
+const DEFLATE_HASH_SIZE: usize = 1 << 15;
+const DEFLATE_MAX_STORED_BLOCK: usize = 65535;
+const LZ77_NONE: usize = usize::MAX;
+
+const DEFLATE_LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const DEFLATE_LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DEFLATE_DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DEFLATE_DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lz77Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: vec![],
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    // Deflate packs values starting at the least significant bit.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= value << self.bit_count;
+        self.bit_count += count;
+
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting at the most significant bit.
+    fn write_huffman_code(&mut self, code: u32, length: u32) {
+        let reversed: u32 = code.reverse_bits() >> (32 - length);
+
+        self.write_bits(reversed, length);
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit_count > 0 {
+            self.write_bits(0, 8 - self.bit_count);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+
+        self.bytes
+    }
+}
+
+// Wraps a deflate stream in a zlib container, using whichever of stored or
+// fixed Huffman blocks produces the smaller output.
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let fixed: Vec<u8> = deflate_fixed_huffman(data);
+    let stored: Vec<u8> = deflate_stored(data);
+
+    let mut zlib: Vec<u8> = vec![0x78, 0x9c];
+    if fixed.len() < stored.len() {
+        zlib.extend(fixed);
+    } else {
+        zlib.extend(stored);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+
+    zlib
+}
+
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut writer: BitWriter = BitWriter::new();
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(DEFLATE_MAX_STORED_BLOCK).collect()
+    };
+
+    for (i, block) in blocks.iter().enumerate() {
+        let is_final: bool = i == blocks.len() - 1;
+
+        writer.write_bits(is_final as u32, 1);
+        writer.write_bits(0b00, 2);
+        writer.align_to_byte();
+        writer.write_bits(block.len() as u32, 16);
+        writer.write_bits(!(block.len() as u32) & 0xffff, 16);
+        for &byte in block.iter() {
+            writer.write_bits(byte as u32, 8);
+        }
+    }
+
+    writer.finish()
+}
+
+fn deflate_fixed_huffman(data: &[u8]) -> Vec<u8> {
+    let mut writer: BitWriter = BitWriter::new();
+
+    writer.write_bits(1, 1);
+    writer.write_bits(0b01, 2);
+
+    for token in lz77_tokens(data) {
+        match token {
+            Lz77Token::Literal(byte) => write_fixed_literal(&mut writer, byte as u32),
+            Lz77Token::Match { length, distance } => {
+                let length_index: usize = deflate_code_index(&DEFLATE_LENGTH_BASE, length);
+                write_fixed_literal(&mut writer, 257 + length_index as u32);
+                writer.write_bits(
+                    (length - DEFLATE_LENGTH_BASE[length_index] as usize) as u32,
+                    DEFLATE_LENGTH_EXTRA_BITS[length_index] as u32,
+                );
+
+                let distance_index: usize = deflate_code_index(&DEFLATE_DISTANCE_BASE, distance);
+                writer.write_huffman_code(distance_index as u32, 5);
+                writer.write_bits(
+                    (distance - DEFLATE_DISTANCE_BASE[distance_index] as usize) as u32,
+                    DEFLATE_DISTANCE_EXTRA_BITS[distance_index] as u32,
+                );
+            }
+        }
+    }
+
+    write_fixed_literal(&mut writer, 256);
+
+    writer.finish()
+}
+
+fn write_fixed_literal(writer: &mut BitWriter, symbol: u32) {
+    let (code, length): (u32, u32) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+
+    writer.write_huffman_code(code, length);
+}
+
+fn deflate_code_index(bases: &[u16], value: usize) -> usize {
+    bases
+        .iter()
+        .rposition(|&base| base as usize <= value)
+        .expect("Value below the smallest deflate code base")
+}
+
+// Greedy LZ77 matching using hash chains over three byte prefixes.
+fn lz77_tokens(data: &[u8]) -> Vec<Lz77Token> {
+    let mut head: Vec<usize> = vec![LZ77_NONE; DEFLATE_HASH_SIZE];
+    let mut previous: Vec<usize> = vec![LZ77_NONE; DEFLATE_WINDOW_SIZE];
+    let mut tokens: Vec<Lz77Token> = vec![];
+
+    let mut i: usize = 0;
+    while i < data.len() {
+        let mut best_length: usize = 0;
+        let mut best_distance: usize = 0;
+
+        if i + DEFLATE_MIN_MATCH <= data.len() {
+            let maximum_length: usize = DEFLATE_MAX_MATCH.min(data.len() - i);
+            let mut candidate: usize = head[lz77_hash(data, i)];
+            let mut chain: usize = 0;
+
+            while candidate != LZ77_NONE
+                && i - candidate <= DEFLATE_WINDOW_SIZE
+                && chain < DEFLATE_MAX_CHAIN
+            {
+                let length: usize = data[candidate..]
+                    .iter()
+                    .zip(data[i..i + maximum_length].iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == maximum_length {
+                        break;
+                    }
+                }
+
+                let next: usize = previous[candidate % DEFLATE_WINDOW_SIZE];
+                if next == LZ77_NONE || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= DEFLATE_MIN_MATCH {
+            tokens.push(Lz77Token::Match {
+                length: best_length,
+                distance: best_distance,
+            });
+            for position in i..i + best_length {
+                lz77_insert(data, position, &mut head, &mut previous);
+            }
+            i += best_length;
+        } else {
+            tokens.push(Lz77Token::Literal(data[i]));
+            lz77_insert(data, i, &mut head, &mut previous);
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+fn lz77_hash(data: &[u8], i: usize) -> usize {
+    (((data[i] as usize) << 10) ^ ((data[i + 1] as usize) << 5) ^ data[i + 2] as usize)
+        & (DEFLATE_HASH_SIZE - 1)
+}
+
+fn lz77_insert(data: &[u8], i: usize, head: &mut [usize], previous: &mut [usize]) {
+    if i + DEFLATE_MIN_MATCH <= data.len() {
+        let key: usize = lz77_hash(data, i);
+        previous[i % DEFLATE_WINDOW_SIZE] = head[key];
+        head[key] = i;
+    }
+}
+
+// RENDERER
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Renderer {
+    threads: usize,
+}
+
+impl Renderer {
+    fn new() -> Self {
+        let threads: usize = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1);
+
+        Renderer { threads }
+    }
+
+    fn with_threads(threads: usize) -> Self {
+        Renderer {
+            threads: threads.max(1),
+        }
+    }
+
+    fn render<F>(&self, width: usize, height: usize, shade: F) -> Canvas
+    where
+        F: Fn(usize, usize) -> Color + Sync,
+    {
+        let cancellation: CancellationToken = CancellationToken::new();
+
+        self.render_with_progress(width, height, &cancellation, |_| {}, shade)
+            .canvas
+    }
+
+    // Rows are handed out one at a time to whichever thread is free. Each
+    // pixel only depends on its own coordinates, so the canvas comes out the
+    // same no matter how many threads render it.
+    //
+    // Cancelling stops threads from starting new rows. Rows that were never
+    // started stay black in the returned canvas.
+    fn render_with_progress<F, O>(
+        &self,
+        width: usize,
+        height: usize,
+        cancellation: &CancellationToken,
+        observer: O,
+        shade: F,
+    ) -> RenderOutput
+    where
+        F: Fn(usize, usize) -> Color + Sync,
+        O: Fn(RenderProgress) + Sync,
+    {
+        let mut canvas: Canvas = Canvas::new(width, height);
+        let threads: usize = self.threads.min(height).max(1);
+        let start: Instant = Instant::now();
+        let completed_rows: Mutex<usize> = Mutex::new(0);
+
+        {
+            let rows: Mutex<Enumerate<ChunksExactMut<Color>>> =
+                Mutex::new(canvas.rows_mut().enumerate());
+
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(|| loop {
+                        if cancellation.is_cancelled() {
+                            break;
+                        }
+
+                        let next: Option<(usize, &mut [Color])> =
+                            rows.lock().expect("Row queue was poisoned").next();
+
+                        match next {
+                            Some((y, row)) => {
+                                for (x, color) in row.iter_mut().enumerate() {
+                                    *color = shade(x, y);
+                                }
+                            }
+                            None => break,
+                        }
+
+                        // Reporting while holding the lock keeps the counts
+                        // the observer sees in order.
+                        let mut completed: MutexGuard<usize> =
+                            completed_rows.lock().expect("Progress was poisoned");
+                        *completed += 1;
+                        observer(RenderProgress {
+                            completed_rows: *completed,
+                            total_rows: height,
+                            elapsed: start.elapsed(),
+                        });
+                    });
+                }
+            });
+        }
+
+        let completed: usize = *completed_rows.lock().expect("Progress was poisoned");
+
+        RenderOutput {
+            canvas,
+            // A zero width canvas has no rows to complete, so the row count
+            // alone cannot tell a cancelled render from a finished one.
+            cancelled: cancellation.is_cancelled() && completed < height,
+        }
+    }
+}
+
+struct RenderOutput {
+    canvas: Canvas,
+    cancelled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RenderProgress {
+    completed_rows: usize,
+    total_rows: usize,
+    elapsed: Duration,
+}
+
+impl RenderProgress {
+    fn fraction(self) -> f32 {
+        if self.total_rows == 0 {
+            1.0
+        } else {
+            self.completed_rows as f32 / self.total_rows as f32
+        }
+    }
+
+    // Assumes the remaining rows take as long on average as the finished ones.
+    fn eta(self) -> Option<Duration> {
+        if self.completed_rows == 0 {
+            return None;
+        }
+
+        let remaining_rows: usize = self.total_rows.saturating_sub(self.completed_rows);
+
+        Some(
+            self.elapsed
+                .mul_f64(remaining_rows as f64 / self.completed_rows as f64),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    fn new() -> Self {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+// PROGRESS BAR
+
+struct ProgressBar {
+    width: usize,
+}
+
+impl ProgressBar {
+    fn new(width: usize) -> Self {
+        ProgressBar { width }
+    }
+
+    fn format(&self, progress: RenderProgress) -> String {
+        let filled: usize = (RenderProgress::fraction(progress) * self.width as f32) as usize;
+        let eta: String = match RenderProgress::eta(progress) {
+            Some(eta) => format_duration(eta),
+            None => "--".to_string(),
+        };
+
+        format!(
+            "[{}{}] {:>3}% {}/{} rows, {} elapsed, {} left",
+            "#".repeat(filled),
+            " ".repeat(self.width - filled),
+            (RenderProgress::fraction(progress) * 100.0) as usize,
+            progress.completed_rows,
+            progress.total_rows,
+            format_duration(progress.elapsed),
+            eta
+        )
+    }
+
+    // Redraws the bar in place. Callers pass stderr so the bar does not mix
+    // with image data written to stdout.
+    fn update<W: Write>(&self, output: &mut W, progress: RenderProgress) -> Result<(), String> {
+        write!(output, "\r{}", self.format(progress)).map_err(|error| error.to_string())?;
+        output.flush().map_err(|error| error.to_string())
+    }
+
+    // Moves past the bar once the render returns. The last update cannot do
+    // this itself because a cancelled render never reaches its final row.
+    fn finish<W: Write>(&self, output: &mut W) -> Result<(), String> {
+        writeln!(output).map_err(|error| error.to_string())
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds: f64 = duration.as_secs_f64();
+
+    if seconds < 60.0 {
+        format!("{:.1}s", seconds)
+    } else {
+        let whole_seconds: u64 = duration.as_secs();
+        format!("{}m{:02}s", whole_seconds / 60, whole_seconds % 60)
+    }
+}
+
+// SUPERSAMPLING
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SamplePattern {
+    Grid,
+    Jittered,
+    RotatedGrid,
+    Halton,
+    Sobol,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReconstructionFilter {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+impl ReconstructionFilter {
+    // Half the width of the square each pixel's samples are spread over.
+    fn radius(self) -> f32 {
+        match self {
+            ReconstructionFilter::Box => 0.5,
+            ReconstructionFilter::Tent => 1.0,
+            ReconstructionFilter::Gaussian => 1.5,
+            ReconstructionFilter::Mitchell => 2.0,
+        }
+    }
+
+    fn weight(self, dx: f32, dy: f32) -> f32 {
+        Self::weight_1d(self, dx) * Self::weight_1d(self, dy)
+    }
+
+    fn weight_1d(self, distance: f32) -> f32 {
+        let distance: f32 = distance.abs();
+        let radius: f32 = Self::radius(self);
+
+        if distance > radius {
+            return 0.0;
+        }
+
+        match self {
+            ReconstructionFilter::Box => 1.0,
+            ReconstructionFilter::Tent => 1.0 - distance / radius,
+            ReconstructionFilter::Gaussian => {
+                // Offset so the curve reaches zero at the radius.
+                const ALPHA: f32 = 2.0;
+                (-ALPHA * distance * distance).exp() - (-ALPHA * radius * radius).exp()
+            }
+            ReconstructionFilter::Mitchell => {
+                // Mitchell-Netravali with B = C = 1/3.
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                let x: f32 = distance;
+
+                if x < 1.0 {
+                    ((12.0 - 9.0 * B - 6.0 * C) * x * x * x
+                        + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+                        + (6.0 - 2.0 * B))
+                        / 6.0
+                } else {
+                    ((-B - 6.0 * C) * x * x * x
+                        + (6.0 * B + 30.0 * C) * x * x
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Supersampler {
+    samples_per_axis: usize,
+    pattern: SamplePattern,
+    filter: ReconstructionFilter,
+    seed: u64,
+}
+
+impl Supersampler {
+    fn new(samples_per_axis: usize, pattern: SamplePattern, filter: ReconstructionFilter) -> Self {
+        Supersampler {
+            samples_per_axis: samples_per_axis.max(1),
+            pattern,
+            filter,
+            seed: 0,
+        }
+    }
+
+    fn with_seed(sampler: Self, seed: u64) -> Self {
+        Supersampler { seed, ..sampler }
+    }
+
+    // Sample positions in the unit square for pixel (x, y). The grid
+    // patterns put exactly one sample in each cell of an n by n grid. The
+    // low-discrepancy patterns use the first n * n points of their sequence,
+    // shifted by a random amount per pixel so neighbors don't share a
+    // pattern.
+    fn sample_offsets(&self, x: usize, y: usize) -> Vec<(f32, f32)> {
+        let n: usize = self.samples_per_axis;
+        let cell: f32 = 1.0 / n as f32;
+        let mut offsets: Vec<(f32, f32)> = Vec::with_capacity(n * n);
+
+        let mut pixel_rng: Rng = Rng::for_pixel(self.seed, x, y, 0);
+        let shift: (f32, f32) = (Rng::next_f32(&mut pixel_rng), Rng::next_f32(&mut pixel_rng));
+
+        for j in 0..n {
+            for i in 0..n {
+                let index: usize = j * n + i;
+
+                let offset: (f32, f32) = match self.pattern {
+                    SamplePattern::Grid => ((i as f32 + 0.5) * cell, (j as f32 + 0.5) * cell),
+                    SamplePattern::Jittered => {
+                        let mut rng: Rng = Rng::for_pixel(self.seed, x, y, index);
+                        (
+                            (i as f32 + Rng::next_f32(&mut rng)) * cell,
+                            (j as f32 + Rng::next_f32(&mut rng)) * cell,
+                        )
+                    }
+                    SamplePattern::RotatedGrid => {
+                        // A grid tilted by atan(1/n) so that no two samples
+                        // share a row or column, which helps most on
+                        // near-vertical and near-horizontal edges.
+                        (
+                            (i as f32 + (j as f32 + 0.5) * cell) * cell,
+                            (j as f32 + (n - 1 - i) as f32 * cell + 0.5 * cell) * cell,
+                        )
+                    }
+                    SamplePattern::Halton => (
+                        (halton(index as u32, 0) + shift.0).fract(),
+                        (halton(index as u32, 1) + shift.1).fract(),
+                    ),
+                    SamplePattern::Sobol => (
+                        (sobol(index as u32, 0) + shift.0).fract(),
+                        (sobol(index as u32, 1) + shift.1).fract(),
+                    ),
+                };
+                offsets.push(offset);
+            }
+        }
+
+        offsets
+    }
+
+    // Samples are spread over the filter's footprint around the pixel
+    // center, which for the wider filters reaches into neighboring pixels.
+    // The shade function takes continuous canvas coordinates, where pixel
+    // (x, y) covers x..x + 1 and y..y + 1.
+    fn shade_pixel<F>(&self, x: usize, y: usize, shade: F) -> Color
+    where
+        F: Fn(f32, f32) -> Color,
+    {
+        let radius: f32 = ReconstructionFilter::radius(self.filter);
+        let center_x: f32 = x as f32 + 0.5;
+        let center_y: f32 = y as f32 + 0.5;
+        let mut total: Color = Color::new(0.0, 0.0, 0.0);
+        let mut total_weight: f32 = 0.0;
+
+        for (u, v) in self.sample_offsets(x, y) {
+            let dx: f32 = (u - 0.5) * 2.0 * radius;
+            let dy: f32 = (v - 0.5) * 2.0 * radius;
+            let weight: f32 = ReconstructionFilter::weight(self.filter, dx, dy);
+
+            total = total + shade(center_x + dx, center_y + dy) * weight;
+            total_weight += weight;
+        }
+
+        if total_weight > 0.0 {
+            total * (1.0 / total_weight)
+        } else {
+            shade(center_x, center_y)
+        }
+    }
+}
+
+// RANDOM
+
+// A PCG32 generator (O'Neill, "PCG: A Family of Simple Fast Space-Efficient
+// Statistically Good Algorithms for Random Number Generation"). Renders seed
+// one generator per pixel and sample, so the output never depends on which
+// thread rendered a pixel or in what order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    fn new(seed: u64, stream: u64) -> Self {
+        let mut rng: Rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        Self::next_u32(&mut rng);
+        rng.state = rng.state.wrapping_add(seed);
+        Self::next_u32(&mut rng);
+
+        rng
+    }
+
+    fn for_pixel(seed: u64, x: usize, y: usize, sample: usize) -> Self {
+        let pixel: u64 = splitmix64(seed ^ splitmix64(((y as u64) << 32) | x as u64));
+
+        Self::new(splitmix64(pixel ^ sample as u64), pixel)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+        let state: u64 = self.state;
+        self.state = state.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+
+        let xorshifted: u32 = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation: u32 = (state >> 59) as u32;
+
+        xorshifted.rotate_right(rotation)
+    }
+
+    // Uses the top 24 bits so every value is exactly representable and the
+    // result is always below 1.
+    fn next_f32(&mut self) -> f32 {
+        (Self::next_u32(self) >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z: u64 = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
+
+// LOW-DISCREPANCY SEQUENCES
+
+const HALTON_PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+// Each dimension of the Halton sequence is the radical inverse of the index
+// in the next prime base.
+fn halton(index: u32, dimension: usize) -> f32 {
+    let base: u32 = HALTON_PRIMES[dimension % HALTON_PRIMES.len()];
+    let inverse_base: f64 = 1.0 / base as f64;
+    let mut index: u32 = index;
+    let mut factor: f64 = inverse_base;
+    let mut result: f64 = 0.0;
+
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+
+    (result as f32).min(1.0 - f32::EPSILON / 2.0)
+}
+
+// Primitive polynomial degrees, coefficients and initial direction numbers
+// for Sobol dimensions 1 to 5, from Joe and Kuo's new-joe-kuo-6.21201 table.
+// Dimension 0 is the base 2 radical inverse.
+const SOBOL_PARAMETERS: [(u32, u32, [u32; 4]); 5] = [
+    (1, 0, [1, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0]),
+    (3, 1, [1, 3, 1, 0]),
+    (3, 2, [1, 1, 1, 0]),
+    (4, 1, [1, 1, 3, 3]),
+];
+
+fn sobol(index: u32, dimension: usize) -> f32 {
+    let directions: [u32; 32] = sobol_directions(dimension % (SOBOL_PARAMETERS.len() + 1));
+    let mut result: u32 = 0;
+
+    for (bit, direction) in directions.iter().enumerate() {
+        if index & (1 << bit) != 0 {
+            result ^= direction;
+        }
+    }
+
+    (result >> 8) as f32 / (1 << 24) as f32
+}
+
+fn sobol_directions(dimension: usize) -> [u32; 32] {
+    let mut directions: [u32; 32] = [0; 32];
+
+    if dimension == 0 {
+        for (bit, direction) in directions.iter_mut().enumerate() {
+            *direction = 1 << (31 - bit);
+        }
+        return directions;
+    }
+
+    let (degree, coefficients, initial): (u32, u32, [u32; 4]) = SOBOL_PARAMETERS[dimension - 1];
+    let degree: usize = degree as usize;
+
+    for bit in 0..32 {
+        directions[bit] = if bit < degree {
+            initial[bit] << (31 - bit)
+        } else {
+            let mut direction: u32 =
+                directions[bit - degree] ^ (directions[bit - degree] >> degree);
+            for k in 1..degree {
+                if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                    direction ^= directions[bit - k];
+                }
+            }
+            direction
+        };
+    }
+
+    directions
+}
+
+// ADAPTIVE SAMPLING
+
+// Each level splits a pixel into four, so eight levels already allow 65536
+// sub-pixels. Deeper limits would overflow the sample counts.
+const ADAPTIVE_MAX_DEPTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct AdaptiveSampler {
+    threshold: f32,
+    max_depth: usize,
+}
+
+struct AdaptiveRender {
+    canvas: Canvas,
+    // Extra samples taken beyond the pixel corners, scaled so white means the
+    // pixel was subdivided all the way to the maximum depth.
+    debug: Canvas,
+}
+
+impl AdaptiveSampler {
+    fn new(threshold: f32, max_depth: usize) -> Self {
+        AdaptiveSampler {
+            threshold,
+            max_depth: max_depth.min(ADAPTIVE_MAX_DEPTH),
+        }
+    }
+
+    // Shades every pixel corner once, then only subdivides pixels whose
+    // corners differ by more than the threshold. The shade function takes
+    // continuous canvas coordinates, where pixel (x, y) covers x..x + 1 and
+    // y..y + 1.
+    fn render<F>(
+        &self,
+        renderer: &Renderer,
+        width: usize,
+        height: usize,
+        shade: F,
+    ) -> AdaptiveRender
+    where
+        F: Fn(f32, f32) -> Color + Sync,
+    {
+        let corners: Canvas = Renderer::render(renderer, width + 1, height + 1, |x, y| {
+            shade(x as f32, y as f32)
+        });
+        let extra_samples: Vec<AtomicUsize> =
+            (0..width * height).map(|_| AtomicUsize::new(0)).collect();
+
+        let canvas: Canvas = Renderer::render(renderer, width, height, |x, y| {
+            let corner = |dx: usize, dy: usize| -> Color {
+                Canvas::pixel_at(&corners, x + dx, y + dy).expect("Missing pixel corner")
+            };
+            let mut samples: usize = 0;
+            let color: Color = self.refine(
+                &shade,
+                (x as f32, y as f32),
+                1.0,
+                [corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1)],
+                0,
+                &mut samples,
+            );
+
+            extra_samples[y * width + x].store(samples, Ordering::SeqCst);
+            color
+        });
+
+        let maximum: f32 = Self::maximum_extra_samples(*self).max(1) as f32;
+        let mut debug: Canvas = Canvas::new(width, height);
+        for (x, y, color) in Canvas::pixels_mut(&mut debug) {
+            let level: f32 = extra_samples[y * width + x].load(Ordering::SeqCst) as f32 / maximum;
+            *color = Color::new(level, level, level);
+        }
+
+        AdaptiveRender { canvas, debug }
+    }
+
+    // Corners are ordered top left, top right, bottom left, bottom right.
+    fn refine<F>(
+        &self,
+        shade: &F,
+        origin: (f32, f32),
+        size: f32,
+        corners: [Color; 4],
+        depth: usize,
+        samples: &mut usize,
+    ) -> Color
+    where
+        F: Fn(f32, f32) -> Color,
+    {
+        if depth >= self.max_depth || color_contrast(&corners) <= self.threshold {
+            return (corners[0] + corners[1] + corners[2] + corners[3]) * 0.25;
+        }
+
+        let (x, y): (f32, f32) = origin;
+        let half: f32 = size / 2.0;
+        let top: Color = shade(x + half, y);
+        let left: Color = shade(x, y + half);
+        let center: Color = shade(x + half, y + half);
+        let right: Color = shade(x + size, y + half);
+        let bottom: Color = shade(x + half, y + size);
+        *samples += 5;
+
+        let quadrants: [((f32, f32), [Color; 4]); 4] = [
+            ((x, y), [corners[0], top, left, center]),
+            ((x + half, y), [top, corners[1], center, right]),
+            ((x, y + half), [left, center, corners[2], bottom]),
+            ((x + half, y + half), [center, right, bottom, corners[3]]),
+        ];
+
+        let mut total: Color = Color::new(0.0, 0.0, 0.0);
+        for &(quadrant_origin, quadrant_corners) in quadrants.iter() {
+            total = total
+                + self.refine(
+                    shade,
+                    quadrant_origin,
+                    half,
+                    quadrant_corners,
+                    depth + 1,
+                    samples,
+                );
+        }
+
+        total * 0.25
+    }
+
+    // Each subdivision adds five samples and splits into four quadrants.
+    fn maximum_extra_samples(self) -> usize {
+        (0..self.max_depth)
+            .map(|depth| 5 * 4_usize.pow(depth as u32))
+            .sum()
+    }
+}
+
+// The largest difference in any channel between any two of the colors.
+fn color_contrast(colors: &[Color]) -> f32 {
+    let mut contrast: f32 = 0.0;
+
+    for a in colors.iter() {
+        for b in colors.iter() {
+            contrast = contrast
+                .max((a.red - b.red).abs())
+                .max((a.green - b.green).abs())
+                .max((a.blue - b.blue).abs());
+        }
+    }
+
+    contrast
+}
+
+// ENVIRONMENT MAP
+
+// An equirectangular image wrapped around the scene. The center of the
+// image faces -z, the top row faces +y and the left and right edges meet
+// behind the viewer at +z.
+struct EnvironmentMap {
+    canvas: Canvas,
+    // Cumulative distributions for picking a row, then a column within that
+    // row, in proportion to each pixel's share of the total light. They are
+    // summed in f64 so a few extremely bright pixels cannot overflow them.
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<Vec<f64>>,
+    pixel_weights: Vec<f64>,
+    total_weight: f64,
+}
+
+impl EnvironmentMap {
+    fn new(canvas: Canvas) -> Self {
+        let width: usize = canvas.width;
+        let height: usize = canvas.height;
+
+        // Sampling and lookups need at least one pixel to land on.
+        assert!(width > 0 && height > 0, "Environment map must not be empty");
+
+        let mut pixel_weights: Vec<f64> = Vec::with_capacity(width * height);
+
+        // Rows near the poles cover less of the sphere, so their weight is
+        // scaled by sin(theta). Pixels with infinite, NaN or negative
+        // luminance get no weight. A black map falls back to sampling the
+        // sphere uniformly.
+        let is_black: bool = canvas
+            .pixels
+            .iter()
+            .all(|color| environment_luminance(color) <= 0.0);
+
+        for (y, row) in Canvas::rows(&canvas).enumerate() {
+            let theta: f64 = (y as f64 + 0.5) / height as f64 * std::f64::consts::PI;
+
+            for color in row {
+                let luminance: f64 = if is_black {
+                    1.0
+                } else {
+                    environment_luminance(color)
+                };
+                pixel_weights.push(luminance * theta.sin());
+            }
+        }
+
+        let mut row_cdf: Vec<f64> = vec![0.0];
+        let mut column_cdfs: Vec<Vec<f64>> = Vec::with_capacity(height);
+
+        for y in 0..height {
+            let mut column_cdf: Vec<f64> = vec![0.0];
+            for x in 0..width {
+                let previous: f64 = column_cdf[x];
+                column_cdf.push(previous + pixel_weights[y * width + x]);
+            }
+
+            let row_weight: f64 = column_cdf[width];
+            row_cdf.push(row_cdf[y] + row_weight);
+            column_cdfs.push(column_cdf);
+        }
+
+        let total_weight: f64 = row_cdf[height];
+
+        EnvironmentMap {
+            canvas,
+            row_cdf,
+            column_cdfs,
+            pixel_weights,
+            total_weight,
+        }
+    }
+
+    fn read_from_file(path: &Path) -> Result<EnvironmentMap, String> {
+        let extension: String = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let canvas: Canvas = match extension.as_str() {
+            "hdr" => Canvas::read_hdr_from_file(path)?,
+            "pfm" => Canvas::read_pfm_from_file(path)?,
+            _ => return Err(format!("Unsupported environment map: {}", path.display())),
+        };
+
+        if canvas.width == 0 || canvas.height == 0 {
+            return Err(format!("Empty environment map: {}", path.display()));
+        }
+
+        Ok(Self::new(canvas))
+    }
+
+    fn direction_to_uv(direction: Tuple) -> (f32, f32) {
+        let direction: Tuple = Tuple::normalize(direction);
+        let u: f32 = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v: f32 = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+        (u.rem_euclid(1.0), v)
+    }
+
+    fn uv_to_direction(u: f32, v: f32) -> Tuple {
+        let phi: f32 = (u - 0.5) * 2.0 * PI;
+        let theta: f32 = v * PI;
+
+        Tuple::vector(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    fn pixel_for(&self, direction: Tuple) -> (usize, usize) {
+        let (u, v): (f32, f32) = Self::direction_to_uv(direction);
+        let x: usize = ((u * self.canvas.width as f32) as usize).min(self.canvas.width - 1);
+        let y: usize = ((v * self.canvas.height as f32) as usize).min(self.canvas.height - 1);
+
+        (x, y)
+    }
+
+    // The background color seen by a ray that misses everything.
+    fn color_at(&self, direction: Tuple) -> Color {
+        let (x, y): (usize, usize) = self.pixel_for(direction);
+
+        Canvas::pixel_at(&self.canvas, x, y).expect("Environment pixel out of range")
+    }
+
+    // Picks a direction with probability proportional to how much light
+    // comes from it. Takes two uniform random numbers in [0, 1) and returns
+    // the direction, its color and its probability density per steradian.
+    fn sample(&self, u1: f32, u2: f32) -> (Tuple, Color, f32) {
+        let y: usize = sample_cdf(&self.row_cdf, u2);
+        let column_cdf: &[f64] = &self.column_cdfs[y];
+        let x: usize = sample_cdf(column_cdf, u1);
+
+        // Spread the sample out within the chosen pixel.
+        let row_start: f64 = self.row_cdf[y] / self.total_weight;
+        let row_end: f64 = self.row_cdf[y + 1] / self.total_weight;
+        let column_start: f64 = column_cdf[x] / column_cdf[self.canvas.width];
+        let column_end: f64 = column_cdf[x + 1] / column_cdf[self.canvas.width];
+        let row_offset: f64 = ((u2 as f64 - row_start) / (row_end - row_start)).clamp(0.0, 0.999);
+        let column_offset: f64 =
+            ((u1 as f64 - column_start) / (column_end - column_start)).clamp(0.0, 0.999);
+
+        let u: f32 = ((x as f64 + column_offset) / self.canvas.width as f64) as f32;
+        let v: f32 = ((y as f64 + row_offset) / self.canvas.height as f64) as f32;
+        let direction: Tuple = Self::uv_to_direction(u, v);
+        let color: Color =
+            Canvas::pixel_at(&self.canvas, x, y).expect("Sampled pixel out of range");
+
+        (direction, color, self.pdf(direction))
+    }
+
+    fn pdf(&self, direction: Tuple) -> f32 {
+        let (x, y): (usize, usize) = self.pixel_for(direction);
+        let (_, v): (f32, f32) = Self::direction_to_uv(direction);
+        let sin_theta: f32 = (v * PI).sin();
+
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // Density over the unit square of the image, converted to solid
+        // angle. The image spans 2 pi by pi radians.
+        let pixel_count: f64 = (self.canvas.width * self.canvas.height) as f64;
+        let image_pdf: f32 = (self.pixel_weights[y * self.canvas.width + x] / self.total_weight
+            * pixel_count) as f32;
+
+        image_pdf / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// Luminance as a sampling weight. Infinite and NaN pixels would poison every
+// sum they are part of, so they count as black like negative ones.
+fn environment_luminance(color: &Color) -> f64 {
+    let luminance: f32 = Color::luminance(color);
+
+    if luminance.is_finite() && luminance > 0.0 {
+        luminance as f64
+    } else {
+        0.0
+    }
+}
+
+// Finds the bin of a cumulative distribution that a uniform random number
+// falls into, skipping empty bins.
+fn sample_cdf(cdf: &[f64], random: f32) -> usize {
+    let total: f64 = cdf[cdf.len() - 1];
+    let target: f64 = random as f64 * total;
+    let bins: usize = cdf.len() - 1;
+
+    let index: usize = cdf[1..].partition_point(|&value| value <= target);
+
+    index.min(bins - 1)
+}
+
+// MATRICES
+
+#[derive(Clone, Debug)]
+struct Matrix {
+    size: usize,
+    values: Vec<Vec<i32>>,
+}
+
+impl Matrix {
+    fn new() -> Self {
+        const MATRIX_SIZE: usize = 4;
+        let initial_value: i32 = 0;
+
+        Matrix {
+            size: MATRIX_SIZE,
+            values: vec![vec![initial_value; MATRIX_SIZE]; MATRIX_SIZE],
+        }
+    }
+
+    fn get(self: &Self, column: usize, row: usize) -> i32 {
+        self.values[column][row]
+    }
+}
+
+// TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuple_constructor_for_points() {
+        let tuple: Tuple = Tuple::new(4.3, -4.2, 3.1, 1.0);
+        assert_eq!(tuple.x, 4.3);
+        assert_eq!(tuple.y, -4.2);
+        assert_eq!(tuple.z, 3.1);
+        assert_eq!(tuple.w, 1.0);
+
+        let is_point: bool = Tuple::is_point(tuple);
+        assert!(is_point);
+
+        let is_vector: bool = Tuple::is_vector(tuple);
+        assert!(!is_vector);
+    }
+
+    #[test]
+    fn test_tuple_constructor_for_vectors() {
+        let tuple: Tuple = Tuple::new(4.3, -4.2, 3.1, 0.0);
+        assert_eq!(tuple.x, 4.3);
+        assert_eq!(tuple.y, -4.2);
+        assert_eq!(tuple.z, 3.1);
+        assert_eq!(tuple.w, 0.0);
+
+        let is_point: bool = Tuple::is_point(tuple);
+        assert!(!is_point);
+
+        let is_vector: bool = Tuple::is_vector(tuple);
+        assert!(is_vector);
+    }
+
+    #[test]
+    fn test_point_creates_a_point() {
+        let tuple: Tuple = Tuple::point(4.0, -4.0, 3.0);
+        assert_eq!(tuple.x, 4.0);
+        assert_eq!(tuple.y, -4.0);
+        assert_eq!(tuple.z, 3.0);
+        assert_eq!(tuple.w, 1.0);
+
+        let is_point: bool = Tuple::is_point(tuple);
+        assert!(is_point);
+    }
+
+    #[test]
+    fn test_vector_creates_a_vector() {
+        let tuple: Tuple = Tuple::vector(4.0, -4.0, 3.0);
+        assert_eq!(tuple.x, 4.0);
+        assert_eq!(tuple.y, -4.0);
+        assert_eq!(tuple.z, 3.0);
+        assert_eq!(tuple.w, 0.00);
+
+        let is_vector: bool = Tuple::is_vector(tuple);
+        assert!(is_vector);
+    }
+
+    #[test]
+    fn test_add_tuples() {
+        let tuple1: Tuple = Tuple::new(3.0, -2.0, 5.0, 1.0);
+        let tuple2: Tuple = Tuple::new(-2.0, 3.0, 1.0, 0.0);
+        let result: Tuple = tuple1 + tuple2;
+
+        assert_eq!(result.x, 1.0);
+        assert_eq!(result.y, 1.0);
+        assert_eq!(result.z, 6.0);
+        assert_eq!(result.w, 1.0);
+    }
+
+    #[test]
+    fn test_subtract_point_from_point() {
+        let point1: Tuple = Tuple::point(3.0, 2.0, 1.0);
+        let point2: Tuple = Tuple::point(5.0, 6.0, 7.0);
+        let result: Tuple = point1 - point2;
+
+        assert_eq!(result.x, -2.0);
+        assert_eq!(result.y, -4.0);
+        assert_eq!(result.z, -6.0);
+        assert_eq!(result.w, 0.0);
+    }
+
+    #[test]
+    fn test_subtract_vector_from_point() {
+        let point: Tuple = Tuple::point(3.0, 2.0, 1.0);
+        let vector: Tuple = Tuple::vector(5.0, 6.0, 7.0);
+        let result: Tuple = point - vector;
+
+        assert_eq!(result.x, -2.0);
+        assert_eq!(result.y, -4.0);
+        assert_eq!(result.z, -6.0);
+        assert_eq!(result.w, 1.0);
+    }
+
+    #[test]
+    fn test_subtract_vector_from_vector() {
+        let vector1: Tuple = Tuple::vector(3.0, 2.0, 1.0);
+        let vector2: Tuple = Tuple::vector(5.0, 6.0, 7.0);
+        let result: Tuple = vector1 - vector2;
+
+        assert_eq!(result.x, -2.0);
+        assert_eq!(result.y, -4.0);
+        assert_eq!(result.z, -6.0);
+        assert_eq!(result.w, 0.0);
+    }
+
+    #[test]
+    fn test_subtract_vector_from_zero_vector() {
+        let vector1: Tuple = Tuple::vector(0.0, 0.0, 0.0);
+        let vector2: Tuple = Tuple::vector(1.0, -2.0, 3.0);
+        let result: Tuple = vector1 - vector2;
+
+        assert_eq!(result.x, -1.0);
+        assert_eq!(result.y, 2.0);
+        assert_eq!(result.z, -3.0);
+        assert_eq!(result.w, 0.0);
+    }
+
+    #[test]
+    fn test_negate_tuple() {
+        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
+        let result: Tuple = -tuple;
+
+        assert_eq!(result.x, -1.0);
+        assert_eq!(result.y, 2.0);
+        assert_eq!(result.z, -3.0);
+        assert_eq!(result.w, 4.0);
+    }
+
+    #[test]
+    fn test_multiply_tuple_by_scalar() {
+        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
+        let result: Tuple = tuple * 3.5;
+
+        assert_eq!(result.x, 3.5);
+        assert_eq!(result.y, -7.0);
+        assert_eq!(result.z, 10.5);
+        assert_eq!(result.w, -14.0);
+    }
+
+    #[test]
+    fn test_divide_tuple_by_scalar() {
+        let tuple: Tuple = Tuple::new(1.0, -2.0, 3.0, -4.0);
+        let result: Tuple = tuple / 2.0;
+
+        assert_eq!(result.x, 0.5);
+        assert_eq!(result.y, -1.0);
+        assert_eq!(result.z, 1.5);
+        assert_eq!(result.w, -2.0);
+    }
+
+    #[test]
+    fn test_vector_magnitude() {
+        let vector: Tuple = Tuple::vector(1.0, 0.0, 0.0);
+        let result: f32 = Tuple::magnitude(vector);
+
+        assert_eq!(result, 1.0);
+    }
+
+    #[test]
+    fn test_vector_magnitude_pythagoras() {
+        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+
+        let expected: f32 = (14.0 as f32).sqrt();
+        let actual: f32 = Tuple::magnitude(vector);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_normalize_simple_vector() {
+        let vector: Tuple = Tuple::vector(4.0, 0.0, 0.0);
+        let result: Tuple = Tuple::normalize(vector);
+
+        assert_eq!(result.x, 1.0);
+        assert_eq!(result.y, 0.0);
+        assert_eq!(result.z, 0.0);
+        assert_eq!(result.w, 0.0);
+    }
+
+    #[test]
+    fn test_normalize_vector() {
+        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+        let result: Tuple = Tuple::normalize(vector);
+
+        assert_eq!(result.x, 1.0 / (14.0 as f32).sqrt());
+        assert_eq!(result.y, 2.0 / (14.0 as f32).sqrt());
+        assert_eq!(result.z, 3.0 / (14.0 as f32).sqrt());
+        assert_eq!(result.w, 0.0);
+    }
+
+    #[test]
+    fn test_magnitude_of_normalized_vector() {
+        let vector: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+        let normalized_vector: Tuple = Tuple::normalize(vector);
+        let result: f32 = Tuple::magnitude(normalized_vector);
+
+        assert!(float_eq(result, 1.0));
+    }
+
+    #[test]
+    fn test_dot_product() {
+        let vector1: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+        let vector2: Tuple = Tuple::vector(2.0, 3.0, 4.0);
+        let result: f32 = Tuple::dot(vector1, vector2);
+
+        assert_eq!(result, 20.0);
+    }
+
+    #[test]
     fn test_cross_product() {
         let vector1: Tuple = Tuple::vector(1.0, 2.0, 3.0);
         let vector2: Tuple = Tuple::vector(2.0, 3.0, 4.0);
         let result: Tuple = Tuple::cross(vector1, vector2);
 
-        assert_eq!(result.x, -1.0);
-        assert_eq!(result.y, 2.0);
-        assert_eq!(result.z, -1.0);
-        assert_eq!(result.w, 0.0);
-        assert!(Tuple::is_vector(result));
+        assert_eq!(result.x, -1.0);
+        assert_eq!(result.y, 2.0);
+        assert_eq!(result.z, -1.0);
+        assert_eq!(result.w, 0.0);
+        assert!(Tuple::is_vector(result));
+    }
+
+    #[test]
+    fn test_cross_product_order() {
+        let vector1: Tuple = Tuple::vector(1.0, 2.0, 3.0);
+        let vector2: Tuple = Tuple::vector(2.0, 3.0, 4.0);
+        let result: Tuple = Tuple::cross(vector2, vector1);
+
+        assert_eq!(result.x, 1.0);
+        assert_eq!(result.y, -2.0);
+        assert_eq!(result.z, 1.0);
+        assert_eq!(result.w, 0.0);
+        assert!(Tuple::is_vector(result));
+    }
+
+    #[test]
+    fn test_projectile_constructor() {
+        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
+        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
+        let result: Projectile = Projectile::new(position, velocity);
+
+        assert_eq!(result.position, position);
+        assert_eq!(result.velocity, velocity);
+    }
+
+    #[test]
+    fn test_environment_constructor() {
+        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
+        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
+        let result: Environment = Environment::new(gravity, wind);
+
+        assert_eq!(result.gravity, gravity);
+        assert_eq!(result.wind, wind);
+    }
+
+    #[test]
+    fn test_environment_tick() {
+        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
+        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
+        let projectile: Projectile = Projectile::new(position, velocity);
+
+        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
+        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
+        let environment: Environment = Environment::new(gravity, wind);
+
+        let updated_projectile: Projectile = Environment::tick(environment, projectile);
+
+        assert_eq!(updated_projectile.position.x, 0.70710677);
+        assert_eq!(updated_projectile.position.y, 1.7071068);
+        assert_eq!(updated_projectile.velocity.x, 0.6971068);
+        assert_eq!(updated_projectile.velocity.y, 0.60710675);
+    }
+
+    #[test]
+    fn test_environment_tick_repeated() {
+        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
+        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
+        let mut projectile: Projectile = Projectile::new(position, velocity);
+
+        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
+        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
+        let environment: Environment = Environment::new(gravity, wind);
+
+        while projectile.position.y > 0.0 {
+            projectile = Environment::tick(environment, projectile);
+        }
+
+        assert!(projectile.position.y <= 0.0);
+    }
+
+    #[test]
+    fn test_color_constructor() {
+        let color: Color = Color::new(-0.5, 0.4, 1.7);
+
+        assert_eq!(color.red, -0.5);
+        assert_eq!(color.green, 0.4);
+        assert_eq!(color.blue, 1.7);
+    }
+
+    #[test]
+    fn test_color_to_integers_tuple_min() {
+        let color: Color = Color::new(0.0, 0.0, 0.0);
+        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+
+        assert_eq!(red, 0);
+        assert_eq!(green, 0);
+        assert_eq!(blue, 0);
+    }
+
+    #[test]
+    fn test_color_to_integers_tuple_mid() {
+        let color: Color = Color::new(0.5, 0.5, 0.5);
+        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+
+        assert_eq!(red, 128);
+        assert_eq!(green, 128);
+        assert_eq!(blue, 128);
+    }
+
+    #[test]
+    fn test_color_to_integers_tuple_max() {
+        let color: Color = Color::new(1.0, 1.0, 1.0);
+        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+
+        assert_eq!(red, 255);
+        assert_eq!(green, 255);
+        assert_eq!(blue, 255);
+    }
+
+    #[test]
+    fn test_color_to_integers_tuple_edge() {
+        let color: Color = Color::new(-999.0, 999.0, 999.0);
+        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+
+        assert_eq!(red, 0);
+        assert_eq!(green, 255);
+        assert_eq!(blue, 255);
+    }
+
+    #[test]
+    fn test_color_to_string_min() {
+        let color: Color = Color::new(0.0, 0.0, 0.0);
+        let string: String = Color::to_string(&color);
+
+        assert_eq!(string, "0 0 0");
+    }
+
+    #[test]
+    fn test_color_to_string_max() {
+        let color: Color = Color::new(1.0, 1.0, 1.0);
+        let string: String = Color::to_string(&color);
+
+        assert_eq!(string, "255 255 255");
+    }
+
+    #[test]
+    fn test_add_colors() {
+        let color1: Color = Color::new(0.9, 0.6, 0.75);
+        let color2: Color = Color::new(0.7, 0.1, 0.25);
+        let result: Color = color1 + color2;
+
+        assert!(float_eq(result.red, 1.6));
+        assert!(float_eq(result.green, 0.7));
+        assert!(float_eq(result.blue, 1.0));
+    }
+
+    #[test]
+    fn test_sub_colors() {
+        let color1: Color = Color::new(0.9, 0.6, 0.75);
+        let color2: Color = Color::new(0.7, 0.1, 0.25);
+        let result: Color = color1 - color2;
+
+        assert!(float_eq(result.red, 0.2));
+        assert!(float_eq(result.green, 0.5));
+        assert!(float_eq(result.blue, 0.5));
+    }
+
+    #[test]
+    fn test_multiply_color_by_scalar() {
+        let color: Color = Color::new(0.9, 0.6, 0.75);
+        let result: Color = color * 2.0;
+
+        assert!(float_eq(result.red, 1.8));
+        assert!(float_eq(result.green, 1.2));
+        assert!(float_eq(result.blue, 1.5));
+    }
+
+    #[test]
+    fn test_multiply_colors() {
+        let color1: Color = Color::new(1.0, 0.2, 0.4);
+        let color2: Color = Color::new(0.9, 1.0, 0.1);
+        let result: Color = color1 * color2;
+
+        assert!(float_eq(result.red, 0.9));
+        assert!(float_eq(result.green, 0.2));
+        assert!(float_eq(result.blue, 0.04));
+    }
+
+    #[test]
+    fn test_canvas_constructor() {
+        let canvas: Canvas = Canvas::new(255, 240);
+
+        assert_eq!(canvas.width, 255);
+        assert_eq!(canvas.height, 240);
+
+        for pixel in canvas.pixels.iter() {
+            assert_eq!(pixel.red, 0.0);
+            assert_eq!(pixel.green, 0.0);
+            assert_eq!(pixel.blue, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_get_pixel_from_canvas() {
+        let canvas: Canvas = Canvas::new(10, 20);
+        let pixel: Color = Canvas::pixel_at(&canvas, 2, 3).unwrap();
+
+        assert_eq!(pixel.red, 0.0);
+        assert_eq!(pixel.green, 0.0);
+        assert_eq!(pixel.blue, 0.0);
+    }
+
+    #[test]
+    fn test_write_pixel_to_canvas() {
+        let mut canvas: Canvas = Canvas::new(10, 20);
+        let color: Color = Color::new(1.0, 0.0, 0.0);
+        Canvas::write_pixel(&mut canvas, 2, 3, color);
+        let pixel: Color = Canvas::pixel_at(&canvas, 2, 3).unwrap();
+
+        assert_eq!(pixel.red, 1.0);
+        assert_eq!(pixel.green, 0.0);
+        assert_eq!(pixel.blue, 0.0);
+    }
+
+    #[test]
+    fn test_get_pixel_outside_canvas() {
+        let canvas: Canvas = Canvas::new(10, 20);
+
+        assert!(Canvas::pixel_at(&canvas, 10, 0).is_none());
+        assert!(Canvas::pixel_at(&canvas, 0, 20).is_none());
+        assert!(Canvas::pixel_at(&canvas, 9, 19).is_some());
+    }
+
+    #[test]
+    fn test_write_pixel_outside_canvas() {
+        let mut canvas: Canvas = Canvas::new(2, 2);
+        Canvas::write_pixel(&mut canvas, 2, 0, Color::new(1.0, 1.0, 1.0));
+        Canvas::write_pixel(&mut canvas, 0, 2, Color::new(1.0, 1.0, 1.0));
+
+        for pixel in canvas.pixels.iter() {
+            assert_eq!(pixel.red, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_canvas_rows() {
+        let mut canvas: Canvas = Canvas::new(3, 2);
+        Canvas::write_pixel(&mut canvas, 2, 1, Color::new(1.0, 0.0, 0.0));
+
+        let rows: Vec<&[Color]> = Canvas::rows(&canvas).collect();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 3);
+        assert_eq!(rows[1][2].red, 1.0);
+        assert_eq!(Canvas::row(&canvas, 1).unwrap()[2].red, 1.0);
+        assert!(Canvas::row(&canvas, 2).is_none());
+    }
+
+    #[test]
+    fn test_canvas_rows_of_zero_width_canvas() {
+        let canvas: Canvas = Canvas::new(0, 5);
+
+        assert_eq!(canvas.height, 5);
+        assert_eq!(Canvas::rows(&canvas).len(), 0);
+        assert!(Canvas::row(&canvas, 0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn test_canvas_constructor_overflow() {
+        Canvas::new(usize::MAX, 2);
+    }
+
+    #[test]
+    fn test_canvas_row_mut() {
+        let mut canvas: Canvas = Canvas::new(3, 2);
+
+        for color in Canvas::row_mut(&mut canvas, 1).unwrap().iter_mut() {
+            *color = Color::new(0.0, 1.0, 0.0);
+        }
+
+        assert_eq!(Canvas::pixel_at(&canvas, 0, 0).unwrap().green, 0.0);
+        assert_eq!(Canvas::pixel_at(&canvas, 0, 1).unwrap().green, 1.0);
+        assert_eq!(Canvas::pixel_at(&canvas, 2, 1).unwrap().green, 1.0);
+        assert!(Canvas::row_mut(&mut canvas, 2).is_none());
+    }
+
+    #[test]
+    fn test_canvas_pixels_iterator() {
+        let mut canvas: Canvas = Canvas::new(3, 2);
+
+        for (x, y, color) in Canvas::pixels_mut(&mut canvas) {
+            *color = Color::new(x as f32, y as f32, 0.0);
+        }
+
+        let pixels: Vec<(usize, usize, Color)> = Canvas::pixels(&canvas).collect();
+
+        assert_eq!(pixels.len(), 6);
+        for (x, y, color) in pixels {
+            assert_eq!(color.red, x as f32);
+            assert_eq!(color.green, y as f32);
+        }
+        assert_eq!(Canvas::pixel_at(&canvas, 2, 1).unwrap().red, 2.0);
+    }
+
+    #[test]
+    fn test_canvas_rows_mut_across_threads() {
+        let mut canvas: Canvas = Canvas::new(4, 8);
+
+        std::thread::scope(|scope| {
+            for (y, row) in Canvas::rows_mut(&mut canvas).enumerate() {
+                scope.spawn(move || {
+                    for color in row.iter_mut() {
+                        *color = Color::new(y as f32, 0.0, 0.0);
+                    }
+                });
+            }
+        });
+
+        for (_, y, color) in Canvas::pixels(&canvas) {
+            assert_eq!(color.red, y as f32);
+        }
+    }
+
+    #[test]
+    fn test_canvas_to_ppm() {
+        let mut canvas: Canvas = Canvas::new(5, 3);
+
+        let color1: Color = Color::new(1.0, 0.0, 0.0);
+        let color2: Color = Color::new(0.0, 0.5, 0.0);
+        let color3: Color = Color::new(0.0, 0.0, 1.0);
+
+        Canvas::write_pixel(&mut canvas, 0, 0, color1);
+        Canvas::write_pixel(&mut canvas, 2, 1, color2);
+        Canvas::write_pixel(&mut canvas, 4, 2, color3);
+
+        let ppm: String = Canvas::canvas_to_ppm(&canvas);
+
+        let expected_output: String = "P3
+5 3
+255
+255 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+0 0 0 0 0 0 0 128 0 0 0 0 0 0 0
+0 0 0 0 0 0 0 0 0 0 0 0 0 0 255
+"
+        .to_string();
+
+        assert_eq!(ppm, expected_output);
+    }
+
+    #[test]
+    fn test_write_ppm_to_file() {
+        let mut canvas: Canvas = Canvas::new(5, 3);
+
+        let color1: Color = Color::new(1.0, 0.0, 0.0);
+        let color2: Color = Color::new(0.0, 0.5, 0.0);
+        let color3: Color = Color::new(0.0, 0.0, 1.0);
+
+        Canvas::write_pixel(&mut canvas, 0, 0, color1);
+        Canvas::write_pixel(&mut canvas, 2, 1, color2);
+        Canvas::write_pixel(&mut canvas, 4, 2, color3);
+
+        let ppm: String = Canvas::canvas_to_ppm(&canvas);
+
+        Canvas::write_ppm_to_file(ppm);
+
+        let file_exists: bool = Path::new("./assets/canvas.ppm").exists();
+        assert!(file_exists);
+
+        // Clean up
+        fs::remove_file("./assets/canvas.ppm").expect("Failed to remove ppm file.");
     }
 
     #[test]
-    fn test_cross_product_order() {
-        let vector1: Tuple = Tuple::vector(1.0, 2.0, 3.0);
-        let vector2: Tuple = Tuple::vector(2.0, 3.0, 4.0);
-        let result: Tuple = Tuple::cross(vector2, vector1);
+    fn test_write_projectile_ppm_to_file() {
+        let canvas_width: usize = 900;
+        let canvas_height: usize = 550;
+        let mut canvas: Canvas = Canvas::new(canvas_width, canvas_height);
 
-        assert_eq!(result.x, 1.0);
-        assert_eq!(result.y, -2.0);
-        assert_eq!(result.z, 1.0);
-        assert_eq!(result.w, 0.0);
-        assert!(Tuple::is_vector(result));
+        let starting_position: Tuple = Tuple::point(0.0, 1.0, 0.0);
+        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.8, 0.0)) * 11.25;
+        let mut projectile: Projectile = Projectile::new(starting_position, velocity);
+
+        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
+        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
+        let environment: Environment = Environment::new(gravity, wind);
+        let color: Color = Color::new(0.5, 0.0, 0.5);
+
+        while projectile.position.y > 0.0 {
+            projectile = Environment::tick(environment, projectile);
+            Canvas::write_pixel(
+                &mut canvas,
+                projectile.position.x as usize,
+                canvas_height - (projectile.position.y as usize),
+                color,
+            );
+        }
+
+        let ppm: String = Canvas::canvas_to_ppm(&canvas);
+
+        Canvas::write_ppm_to_file(ppm);
+
+        let file_exists: bool = Path::new("./assets/canvas.ppm").exists();
+        assert!(file_exists);
+
+        // Clean up
+        fs::remove_file("./assets/canvas.ppm").expect("Failed to remove ppm file.");
+    }
+
+    #[test]
+    fn test_canvas_from_plain_ppm() {
+        let ppm: &str = "P3
+3 2
+255
+255 0 0 0 255 0 0 0 255
+0 0 0 128 128 128 255 255 255
+";
+        let canvas: Canvas = Canvas::from_ppm(ppm.as_bytes()).expect("Failed to parse ppm.");
+
+        assert_eq!(canvas.width, 3);
+        assert_eq!(canvas.height, 2);
+
+        let pixel: Color = Canvas::pixel_at(&canvas, 1, 1).unwrap();
+
+        assert!(float_eq(pixel.red, 128.0 / 255.0));
+        assert!(float_eq(pixel.green, 128.0 / 255.0));
+        assert!(float_eq(pixel.blue, 128.0 / 255.0));
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_with_comments_and_whitespace() {
+        let ppm: &str = "P3 # plain ppm
+# a comment line
+  2\t1
+\t\t255 # maximum
+255   0 0#first pixel
+\t0 0    255
+";
+        let canvas: Canvas = Canvas::from_ppm(ppm.as_bytes()).expect("Failed to parse ppm.");
+
+        assert_eq!(canvas.width, 2);
+        assert_eq!(canvas.height, 1);
+
+        let pixel: Color = Canvas::pixel_at(&canvas, 1, 0).unwrap();
+
+        assert_eq!(pixel.red, 0.0);
+        assert_eq!(pixel.green, 0.0);
+        assert_eq!(pixel.blue, 1.0);
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_scales_maximum_color_value() {
+        let ppm: &str = "P3\n2 1\n100\n100 50 0 25 75 10\n";
+        let canvas: Canvas = Canvas::from_ppm(ppm.as_bytes()).expect("Failed to parse ppm.");
+
+        let pixel: Color = Canvas::pixel_at(&canvas, 1, 0).unwrap();
+
+        assert!(float_eq(pixel.red, 0.25));
+        assert!(float_eq(pixel.green, 0.75));
+        assert!(float_eq(pixel.blue, 0.1));
+    }
+
+    #[test]
+    fn test_canvas_from_binary_ppm() {
+        let mut ppm: Vec<u8> = b"P6\n# binary\n2 1\n255\n".to_vec();
+        ppm.extend_from_slice(&[255, 0, 0, 0, 51, 255]);
+
+        let canvas: Canvas = Canvas::from_ppm(&ppm).expect("Failed to parse ppm.");
+
+        let pixel: Color = Canvas::pixel_at(&canvas, 1, 0).unwrap();
+
+        assert_eq!(pixel.red, 0.0);
+        assert!(float_eq(pixel.green, 0.2));
+        assert_eq!(pixel.blue, 1.0);
+    }
+
+    #[test]
+    fn test_canvas_from_binary_ppm_with_two_byte_samples() {
+        // A 10 (newline) sample right after the header must be read as data.
+        let mut ppm: Vec<u8> = b"P6 1 1 65535\n".to_vec();
+        ppm.extend_from_slice(&[0xff, 0xff, 0x80, 0x00, 0x00, 0x0a]);
+
+        let canvas: Canvas = Canvas::from_ppm(&ppm).expect("Failed to parse ppm.");
+
+        let pixel: Color = Canvas::pixel_at(&canvas, 0, 0).unwrap();
+
+        assert_eq!(pixel.red, 1.0);
+        assert!(float_eq(pixel.green, 32768.0 / 65535.0));
+        assert!(float_eq(pixel.blue, 10.0 / 65535.0));
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_round_trip() {
+        let mut canvas: Canvas = Canvas::new(5, 3);
+
+        Canvas::write_pixel(&mut canvas, 0, 0, Color::new(1.0, 0.0, 0.0));
+        Canvas::write_pixel(&mut canvas, 4, 2, Color::new(0.0, 0.0, 1.0));
+
+        let ppm: String = Canvas::canvas_to_ppm(&canvas);
+        let result: Canvas = Canvas::from_ppm(ppm.as_bytes()).expect("Failed to parse ppm.");
+
+        assert_eq!(Canvas::canvas_to_ppm(&result), ppm);
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_errors() {
+        assert!(Canvas::from_ppm(b"P5\n1 1\n255\n\0").is_err());
+        assert!(Canvas::from_ppm(b"P3\n2 1\n255\n255 0 0 255").is_err());
+        assert!(Canvas::from_ppm(b"P3\n1 1\n255\n256 0 0").is_err());
+        assert!(Canvas::from_ppm(b"P3\n1 1\n0\n0 0 0").is_err());
+        assert!(Canvas::from_ppm(b"P6\n1 1\n255\n\xff\xff").is_err());
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_rejects_oversized_header() {
+        assert!(Canvas::from_ppm(b"P3\n4294967296 4294967296\n255\n0 0 0").is_err());
+        assert!(Canvas::from_ppm(b"P3\n100000 100000\n255\n0 0 0").is_err());
+        assert!(Canvas::from_ppm(b"P6\n100000 100000\n255\n\0\0\0").is_err());
+        assert!(Canvas::from_ppm(b"P6\n1 1\n65535\n\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_canvas_from_ppm_and_pfm_reject_zero_dimensions() {
+        assert!(Canvas::from_ppm(b"P3\n0 4000000000\n255\n").is_err());
+        assert!(Canvas::from_ppm(b"P6\n4000000000 0\n255\n").is_err());
+        assert!(Canvas::from_pfm(b"PF\n0 4000000000\n-1.0\n").is_err());
+        assert!(Canvas::from_pfm(b"Pf\n4000000000 0\n-1.0\n").is_err());
+
+        let empty: Canvas = Canvas::from_ppm(b"P3\n0 0\n255\n").expect("Failed to read PPM.");
+        assert_eq!((empty.width, empty.height), (0, 0));
+        let empty: Canvas = Canvas::from_pfm(b"PF\n0 0\n-1.0\n").expect("Failed to read PFM.");
+        assert_eq!((empty.width, empty.height), (0, 0));
+    }
+
+    #[test]
+    fn test_read_ppm_from_file() {
+        let path: &Path = Path::new("./assets/read_canvas.ppm");
+        fs::write(path, "P3\n1 1\n255\n0 255 0\n").expect("Unable to write file");
+
+        let canvas: Result<Canvas, String> = Canvas::read_ppm_from_file(path);
+
+        // Clean up
+        fs::remove_file(path).expect("Failed to remove ppm file.");
+
+        let pixel: Color = Canvas::pixel_at(&canvas.expect("Failed to read ppm."), 0, 0).unwrap();
+        assert_eq!(pixel.green, 1.0);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[0xff; 10000]), 0xb623_eb2b);
+    }
+
+    #[test]
+    fn test_zlib_compress_fixed_huffman() {
+        let zlib: Vec<u8> = zlib_compress(b"a");
+
+        assert_eq!(
+            zlib,
+            vec![0x78, 0x9c, 0x4b, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]
+        );
+    }
+
+    #[test]
+    fn test_zlib_compress_stored_when_incompressible() {
+        let data: Vec<u8> = (0..=255).collect();
+        let zlib: Vec<u8> = zlib_compress(&data);
+
+        assert_eq!(&zlib[0..7], &[0x78, 0x9c, 0x01, 0x00, 0x01, 0xff, 0xfe]);
+        assert_eq!(&zlib[7..263], &data[..]);
+        assert_eq!(&zlib[263..], &adler32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn test_zlib_compress_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| (((i * 7) % 251) ^ (i / 300)) as u8)
+            .collect();
+        let zlib: Vec<u8> = zlib_compress(&data);
+
+        assert!(zlib.len() < data.len());
+        assert_eq!(inflate(&zlib[2..zlib.len() - 4]), data);
+    }
+
+    #[test]
+    fn test_deflate_stored_splits_blocks() {
+        let data: Vec<u8> = (0..70_000u32).map(|i| i as u8).collect();
+
+        assert_eq!(inflate(&deflate_stored(&data)), data);
+        assert_eq!(inflate(&deflate_stored(&[])), vec![]);
+    }
+
+    #[test]
+    fn test_lz77_tokens() {
+        let tokens: Vec<Lz77Token> = lz77_tokens(b"abcabcabcd");
+
+        assert_eq!(
+            tokens,
+            vec![
+                Lz77Token::Literal(b'a'),
+                Lz77Token::Literal(b'b'),
+                Lz77Token::Literal(b'c'),
+                Lz77Token::Match {
+                    length: 6,
+                    distance: 3
+                },
+                Lz77Token::Literal(b'd'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canvas_to_png() {
+        let mut canvas: Canvas = Canvas::new(5, 3);
+
+        Canvas::write_pixel(&mut canvas, 0, 0, Color::new(1.0, 0.0, 0.0));
+        Canvas::write_pixel(&mut canvas, 2, 1, Color::new(0.0, 0.5, 0.0));
+        Canvas::write_pixel(&mut canvas, 4, 2, Color::new(0.0, 0.0, 1.0));
+
+        let png: Vec<u8> = Canvas::canvas_to_png(&canvas, PngBitDepth::Eight);
+        let chunks: Vec<(String, Vec<u8>)> = png_chunks(&png);
+
+        assert_eq!(&png[0..8], &PNG_SIGNATURE);
+        assert_eq!(chunks[0].0, "IHDR");
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 5, 0, 0, 0, 3, 8, 2, 0, 0, 0]);
+        assert_eq!(chunks[1].0, "IDAT");
+        assert_eq!(chunks[2], ("IEND".to_string(), vec![]));
+
+        let scanlines: Vec<u8> = inflate(&chunks[1].1[2..chunks[1].1.len() - 4]);
+        assert_eq!(scanlines.len(), 3 * (1 + 5 * 3));
+        assert_eq!(&scanlines[1..4], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn test_canvas_to_png_sixteen_bit() {
+        let mut canvas: Canvas = Canvas::new(1, 1);
+        Canvas::write_pixel(&mut canvas, 0, 0, Color::new(1.0, 0.5, -1.0));
+
+        let png: Vec<u8> = Canvas::canvas_to_png(&canvas, PngBitDepth::Sixteen);
+        let chunks: Vec<(String, Vec<u8>)> = png_chunks(&png);
+
+        assert_eq!(chunks[0].1[8], 16);
+
+        let scanlines: Vec<u8> = inflate(&chunks[1].1[2..chunks[1].1.len() - 4]);
+        assert_eq!(scanlines, vec![0, 0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_filter_png_scanline() {
+        let row: Vec<u8> = vec![10, 20, 30, 11, 21, 31, 12, 22, 32];
+        let previous_row: Vec<u8> = vec![0; 9];
+        let scanline: Vec<u8> = filter_png_scanline(&row, &previous_row, 3);
+
+        assert_eq!(scanline, vec![1, 10, 20, 30, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth_predictor(10, 20, 10), 20);
+        assert_eq!(paeth_predictor(20, 10, 10), 20);
+        assert_eq!(paeth_predictor(10, 20, 30), 10);
+        assert_eq!(paeth_predictor(50, 60, 55), 55);
+    }
+
+    #[test]
+    fn test_write_png_to_file() {
+        let canvas: Canvas = Canvas::new(5, 3);
+        let png: Vec<u8> = Canvas::canvas_to_png(&canvas, PngBitDepth::Eight);
+
+        Canvas::write_png_to_file(png);
+
+        let file_exists: bool = Path::new("./assets/canvas.png").exists();
+        assert!(file_exists);
+
+        // Clean up
+        fs::remove_file("./assets/canvas.png").expect("Failed to remove png file.");
+    }
+
+    #[test]
+    fn test_color_to_rgbe() {
+        assert_eq!(Color::to_rgbe(&Color::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(
+            Color::to_rgbe(&Color::new(1.0, 1.0, 1.0)),
+            [128, 128, 128, 129]
+        );
+        assert_eq!(
+            Color::to_rgbe(&Color::new(0.5, 0.25, -1.0)),
+            [128, 64, 0, 128]
+        );
+        assert_eq!(
+            Color::to_rgbe(&Color::new(10.0, 0.0, 0.0)),
+            [160, 0, 0, 132]
+        );
+    }
+
+    #[test]
+    fn test_color_to_rgbe_saturates_bright_values() {
+        assert_eq!(
+            Color::to_rgbe(&Color::new(3.0e38, 3.0e38, 0.0)),
+            [255, 255, 0, 255]
+        );
+        assert_eq!(
+            Color::to_rgbe(&Color::new(f32::INFINITY, 1.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+
+        let brightest: Color = Color::from_rgbe(Color::to_rgbe(&Color::new(f32::MAX, 0.0, 0.0)));
+
+        assert!(brightest.red.is_finite());
+        assert!(brightest.red > 1.0e38);
+    }
+
+    #[test]
+    fn test_color_from_rgbe() {
+        let black: Color = Color::from_rgbe([0, 0, 0, 0]);
+        let bright: Color = Color::from_rgbe([160, 0, 0, 132]);
+
+        assert_eq!(black.red, 0.0);
+        assert!(float_eq(bright.red, 10.03125));
+        assert!(float_eq(bright.green, 0.03125));
+    }
+
+    #[test]
+    fn test_canvas_to_pfm() {
+        let mut canvas: Canvas = Canvas::new(2, 2);
+        Canvas::write_pixel(&mut canvas, 0, 0, Color::new(1.5, 0.0, 0.0));
+        Canvas::write_pixel(&mut canvas, 1, 1, Color::new(0.0, 0.0, 42.0));
+
+        let pfm: Vec<u8> = Canvas::canvas_to_pfm(&canvas);
+        let header: &[u8] = b"PF\n2 2\n-1.0\n";
+
+        assert_eq!(&pfm[..header.len()], header);
+        assert_eq!(pfm.len(), header.len() + 2 * 2 * 3 * 4);
+
+        // The bottom row comes first.
+        let data: &[u8] = &pfm[header.len()..];
+        assert_eq!(&data[20..24], &42.0_f32.to_le_bytes());
+        assert_eq!(&data[24..28], &1.5_f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_canvas_from_pfm_round_trip() {
+        let mut canvas: Canvas = Canvas::new(3, 2);
+        Canvas::write_pixel(&mut canvas, 0, 0, Color::new(12.5, -0.25, 0.0));
+        Canvas::write_pixel(&mut canvas, 2, 1, Color::new(0.1, 1000.0, 3.0));
+
+        let pfm: Vec<u8> = Canvas::canvas_to_pfm(&canvas);
+        let result: Canvas = Canvas::from_pfm(&pfm).expect("Failed to parse pfm.");
+
+        assert_eq!(result.width, 3);
+        assert_eq!(result.height, 2);
+        assert_eq!(Canvas::pixel_at(&result, 0, 0).unwrap().red, 12.5);
+        assert_eq!(Canvas::pixel_at(&result, 0, 0).unwrap().green, -0.25);
+        assert_eq!(Canvas::pixel_at(&result, 2, 1).unwrap().green, 1000.0);
+        assert_eq!(Canvas::pixel_at(&result, 2, 1).unwrap().blue, 3.0);
+    }
+
+    #[test]
+    fn test_canvas_from_big_endian_grayscale_pfm() {
+        let mut pfm: Vec<u8> = b"Pf\n2 1\n2.0\n".to_vec();
+        pfm.extend_from_slice(&0.25_f32.to_be_bytes());
+        pfm.extend_from_slice(&4.0_f32.to_be_bytes());
+
+        let canvas: Canvas = Canvas::from_pfm(&pfm).expect("Failed to parse pfm.");
+        let pixel: Color = Canvas::pixel_at(&canvas, 1, 0).unwrap();
+
+        assert_eq!(pixel.red, 8.0);
+        assert_eq!(pixel.green, 8.0);
+        assert_eq!(pixel.blue, 8.0);
+    }
+
+    #[test]
+    fn test_canvas_from_pfm_errors() {
+        assert!(Canvas::from_pfm(b"PX\n1 1\n-1.0\n").is_err());
+        assert!(Canvas::from_pfm(b"PF\n1 1\n0.0\n").is_err());
+        assert!(Canvas::from_pfm(b"PF\n1 1\n-1.0\n\0\0\0\0").is_err());
+        assert!(Canvas::from_pfm(b"Pf\n1 1\nnan\n\0\0\0\0").is_err());
+        assert!(Canvas::from_pfm(b"Pf\n1 1\n-inf\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_canvas_from_pfm_and_hdr_reject_oversized_headers() {
+        assert!(Canvas::from_pfm(b"PF\n4294967296 4294967296\n-1.0\n\0\0\0\0").is_err());
+        assert!(Canvas::from_pfm(b"Pf\n100000 100000\n-1.0\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 4294967296 +X 4294967296\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 100000 +X 100\n\x02\x02\0\x64").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 5 +X 100000\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 4000000000 +X 0\n").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 0 +X 4000000000\n").is_err());
+    }
+
+    #[test]
+    fn test_hdr_minimum_scanline_size() {
+        assert_eq!(hdr_minimum_scanline_size(0), 0);
+        assert_eq!(hdr_minimum_scanline_size(4), 16);
+        assert_eq!(hdr_minimum_scanline_size(8), 12);
+        assert_eq!(hdr_minimum_scanline_size(254), 20);
+        assert_eq!(hdr_minimum_scanline_size(0x8000), 0x20000);
+    }
+
+    #[test]
+    fn test_canvas_to_hdr_header() {
+        let canvas: Canvas = Canvas::new(3, 2);
+        let hdr: Vec<u8> = Canvas::canvas_to_hdr(&canvas);
+        let header: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+
+        // Narrow images are stored as flat RGBE pixels.
+        assert_eq!(&hdr[..header.len()], header);
+        assert_eq!(hdr.len(), header.len() + 3 * 2 * 4);
+    }
+
+    #[test]
+    fn test_canvas_to_hdr_run_length_encodes_scanlines() {
+        let canvas: Canvas = Canvas::new(100, 1);
+        let hdr: Vec<u8> = Canvas::canvas_to_hdr(&canvas);
+        let header_length: usize = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 100\n".len();
+
+        assert_eq!(&hdr[header_length..header_length + 4], &[2, 2, 0, 100]);
+        assert_eq!(hdr.len(), header_length + 4 + 4 * 2);
+    }
+
+    #[test]
+    fn test_encode_hdr_channel() {
+        let mut encoded: Vec<u8> = vec![];
+        encode_hdr_channel(&[1, 2, 3, 3, 3, 3, 3, 4, 4, 5], &mut encoded);
+
+        assert_eq!(encoded, vec![2, 1, 2, 128 + 5, 3, 3, 4, 4, 5]);
+
+        let values: Vec<u8> = (0..300)
+            .map(|i| if i < 200 { 7 } else { i as u8 })
+            .collect();
+        let mut scanline: Vec<u8> = vec![2, 2, 1, 44];
+        for _ in 0..4 {
+            encode_hdr_channel(&values, &mut scanline);
+        }
+        let mut data: &[u8] = &scanline;
+
+        let rgbe: Vec<[u8; 4]> = decode_hdr_scanline(&mut data, 300).expect("Failed to decode.");
+        assert!(data.is_empty());
+        for (pixel, &value) in rgbe.iter().zip(values.iter()) {
+            assert_eq!(*pixel, [value; 4]);
+        }
+    }
+
+    #[test]
+    fn test_canvas_from_hdr_round_trip() {
+        for &width in [4, 40].iter() {
+            let mut canvas: Canvas = Canvas::new(width, 3);
+            for x in 0..width {
+                let value: f32 = x as f32 * 0.75;
+                Canvas::write_pixel(&mut canvas, x, 1, Color::new(value, 2.0, 0.01));
+            }
+            Canvas::write_pixel(&mut canvas, 0, 2, Color::new(500.0, 0.0, 0.0));
+
+            let hdr: Vec<u8> = Canvas::canvas_to_hdr(&canvas);
+            let result: Canvas = Canvas::from_hdr(&hdr).expect("Failed to parse hdr.");
+
+            assert_eq!(result.width, width);
+            assert_eq!(result.height, 3);
+            assert_eq!(Canvas::pixel_at(&result, 0, 0).unwrap().red, 0.0);
+            assert!(
+                (Canvas::pixel_at(&result, width - 1, 1).unwrap().red
+                    / ((width - 1) as f32 * 0.75)
+                    - 1.0)
+                    .abs()
+                    < 0.01
+            );
+            assert!((Canvas::pixel_at(&result, 1, 1).unwrap().green / 2.0 - 1.0).abs() < 0.01);
+            assert!((Canvas::pixel_at(&result, 0, 2).unwrap().red / 500.0 - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_canvas_from_hdr_errors() {
+        assert!(Canvas::from_hdr(b"P3\n1 1\n255\n0 0 0").is_err());
+        assert!(
+            Canvas::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err()
+        );
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr(b"#?RADIANCE\n\n-Y 1 +X 2\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_write_pfm_and_hdr_to_file() {
+        let mut canvas: Canvas = Canvas::new(10, 2);
+        Canvas::write_pixel(&mut canvas, 9, 1, Color::new(4.0, 2.0, 1.0));
+        Canvas::write_pfm_to_file(Canvas::canvas_to_pfm(&canvas));
+        Canvas::write_hdr_to_file(Canvas::canvas_to_hdr(&canvas));
+
+        let pfm: Result<Canvas, String> =
+            Canvas::read_pfm_from_file(Path::new("./assets/canvas.pfm"));
+        let hdr: Result<Canvas, String> =
+            Canvas::read_hdr_from_file(Path::new("./assets/canvas.hdr"));
+
+        // Clean up
+        fs::remove_file("./assets/canvas.pfm").expect("Failed to remove pfm file.");
+        fs::remove_file("./assets/canvas.hdr").expect("Failed to remove hdr file.");
+
+        assert_eq!(
+            Canvas::pixel_at(&pfm.expect("Failed to read pfm."), 9, 1)
+                .unwrap()
+                .red,
+            4.0
+        );
+        assert!(float_eq(
+            Canvas::pixel_at(&hdr.expect("Failed to read hdr."), 9, 1)
+                .unwrap()
+                .red,
+            4.0 + 1.0 / 64.0
+        ));
+    }
+
+    #[test]
+    fn test_renderer_thread_count() {
+        assert!(Renderer::new().threads >= 1);
+        assert_eq!(Renderer::with_threads(0).threads, 1);
+        assert_eq!(Renderer::with_threads(6).threads, 6);
+    }
+
+    #[test]
+    fn test_renderer_renders_every_pixel_once() {
+        let calls: AtomicUsize = AtomicUsize::new(0);
+        let canvas: Canvas = Renderer::render(&Renderer::with_threads(4), 7, 5, |x, y| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Color::new(x as f32, y as f32, 0.0)
+        });
+
+        assert_eq!(calls.load(Ordering::SeqCst), 35);
+        for (x, y, color) in Canvas::pixels(&canvas) {
+            assert_eq!(color.red, x as f32);
+            assert_eq!(color.green, y as f32);
+        }
+    }
+
+    #[test]
+    fn test_renderer_is_deterministic_across_thread_counts() {
+        let shade = |x: usize, y: usize| -> Color {
+            let u: f32 = x as f32 / 31.0;
+            let v: f32 = y as f32 / 17.0;
+            Color::new((u * 12.9898 + v * 78.233).sin(), u * v, 1.0 - u)
+        };
+
+        let expected: String =
+            Canvas::canvas_to_ppm(&Renderer::render(&Renderer::with_threads(1), 32, 18, shade));
+
+        for &threads in [2, 3, 8, 64].iter() {
+            let canvas: Canvas = Renderer::render(&Renderer::with_threads(threads), 32, 18, shade);
+            assert_eq!(Canvas::canvas_to_ppm(&canvas), expected);
+        }
+    }
+
+    #[test]
+    fn test_renderer_empty_canvas() {
+        let canvas: Canvas = Renderer::render(&Renderer::with_threads(4), 0, 0, |_, _| {
+            Color::new(1.0, 1.0, 1.0)
+        });
+
+        assert_eq!(canvas.pixels.len(), 0);
+    }
+
+    #[test]
+    fn test_renderer_zero_width_render_is_not_cancelled() {
+        let output: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(2),
+            0,
+            5,
+            &CancellationToken::new(),
+            |_| {},
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+
+        assert!(!output.cancelled);
+        assert_eq!(output.canvas.height, 5);
+        assert_eq!(output.canvas.pixels.len(), 0);
+    }
+
+    #[test]
+    fn test_renderer_reports_progress() {
+        let reports: Mutex<Vec<RenderProgress>> = Mutex::new(vec![]);
+        let cancellation: CancellationToken = CancellationToken::new();
+
+        let output: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(3),
+            4,
+            10,
+            &cancellation,
+            |progress| reports.lock().unwrap().push(progress),
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+        let reports: Vec<RenderProgress> = reports.into_inner().unwrap();
+
+        assert!(!output.cancelled);
+        assert_eq!(reports.len(), 10);
+        for (i, progress) in reports.iter().enumerate() {
+            assert_eq!(progress.completed_rows, i + 1);
+            assert_eq!(progress.total_rows, 10);
+        }
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].elapsed <= pair[1].elapsed));
+        assert_eq!(
+            RenderProgress::eta(reports[9]),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[test]
+    fn test_renderer_cancellation_returns_partial_canvas() {
+        let cancellation: CancellationToken = CancellationToken::new();
+
+        let output: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(1),
+            3,
+            10,
+            &cancellation,
+            |progress| {
+                if progress.completed_rows == 4 {
+                    cancellation.cancel();
+                }
+            },
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+
+        assert!(output.cancelled);
+        assert!(cancellation.is_cancelled());
+        for (_, y, color) in Canvas::pixels(&output.canvas) {
+            assert_eq!(color.red, if y < 4 { 1.0 } else { 0.0 });
+        }
     }
 
     #[test]
-    fn test_projectile_constructor() {
-        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
-        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
-        let result: Projectile = Projectile::new(position, velocity);
+    fn test_renderer_cancelled_before_start() {
+        let cancellation: CancellationToken = CancellationToken::new();
+        let clone: CancellationToken = cancellation.clone();
+        clone.cancel();
+
+        let output: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(4),
+            3,
+            3,
+            &cancellation,
+            |_| panic!("No rows should be rendered."),
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+
+        assert!(output.cancelled);
+        assert!(output.canvas.pixels.iter().all(|color| color.red == 0.0));
+    }
 
-        assert_eq!(result.position, position);
-        assert_eq!(result.velocity, velocity);
+    #[test]
+    fn test_render_progress_fraction_and_eta() {
+        let progress: RenderProgress = RenderProgress {
+            completed_rows: 25,
+            total_rows: 100,
+            elapsed: Duration::from_secs(10),
+        };
+
+        assert_eq!(RenderProgress::fraction(progress), 0.25);
+        assert_eq!(RenderProgress::eta(progress), Some(Duration::from_secs(30)));
+
+        let started: RenderProgress = RenderProgress {
+            completed_rows: 0,
+            total_rows: 100,
+            elapsed: Duration::from_millis(5),
+        };
+
+        assert_eq!(RenderProgress::eta(started), None);
     }
 
     #[test]
-    fn test_environment_constructor() {
-        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
-        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
-        let result: Environment = Environment::new(gravity, wind);
+    fn test_progress_bar_format() {
+        let progress_bar: ProgressBar = ProgressBar::new(10);
+        let progress: RenderProgress = RenderProgress {
+            completed_rows: 3,
+            total_rows: 6,
+            elapsed: Duration::from_millis(1500),
+        };
+
+        assert_eq!(
+            ProgressBar::format(&progress_bar, progress),
+            "[#####     ]  50% 3/6 rows, 1.5s elapsed, 1.5s left"
+        );
+
+        let finished: RenderProgress = RenderProgress {
+            completed_rows: 6,
+            total_rows: 6,
+            elapsed: Duration::from_secs(125),
+        };
+
+        assert_eq!(
+            ProgressBar::format(&progress_bar, finished),
+            "[##########] 100% 6/6 rows, 2m05s elapsed, 0.0s left"
+        );
+    }
 
-        assert_eq!(result.gravity, gravity);
-        assert_eq!(result.wind, wind);
+    #[test]
+    fn test_progress_bar_update_and_finish() {
+        let progress_bar: ProgressBar = ProgressBar::new(4);
+        let output: Mutex<Vec<u8>> = Mutex::new(vec![]);
+
+        let render: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(1),
+            1,
+            2,
+            &CancellationToken::new(),
+            |progress| {
+                let mut output: MutexGuard<Vec<u8>> = output.lock().unwrap();
+                ProgressBar::update(&progress_bar, &mut *output, progress).unwrap();
+            },
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+        let mut output: Vec<u8> = output.into_inner().unwrap();
+        ProgressBar::finish(&progress_bar, &mut output).unwrap();
+        let output: String = String::from_utf8(output).unwrap();
+
+        assert!(!render.cancelled);
+        assert!(output.starts_with("\r[##  ]  50% 1/2 rows, "));
+        assert!(output.contains("\r[####] 100% 2/2 rows, "));
+        assert!(output.ends_with(" left\n"));
+        assert_eq!(output.matches('\n').count(), 1);
     }
 
     #[test]
-    fn test_environment_tick() {
-        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
-        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
-        let projectile: Projectile = Projectile::new(position, velocity);
+    fn test_progress_bar_finish_after_cancelled_render() {
+        let progress_bar: ProgressBar = ProgressBar::new(4);
+        let cancellation: CancellationToken = CancellationToken::new();
+        let output: Mutex<Vec<u8>> = Mutex::new(vec![]);
+
+        let render: RenderOutput = Renderer::render_with_progress(
+            &Renderer::with_threads(1),
+            1,
+            4,
+            &cancellation,
+            |progress| {
+                let mut output: MutexGuard<Vec<u8>> = output.lock().unwrap();
+                ProgressBar::update(&progress_bar, &mut *output, progress).unwrap();
+                cancellation.cancel();
+            },
+            |_, _| Color::new(1.0, 1.0, 1.0),
+        );
+        let mut output: Vec<u8> = output.into_inner().unwrap();
+        ProgressBar::finish(&progress_bar, &mut output).unwrap();
+        let output: String = String::from_utf8(output).unwrap();
+
+        assert!(render.cancelled);
+        assert!(output.starts_with("\r[#   ]  25% 1/4 rows, "));
+        assert!(output.ends_with(" left\n"));
+        assert_eq!(output.matches('\r').count(), 1);
+        assert_eq!(output.matches('\n').count(), 1);
+    }
 
-        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
-        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
-        let environment: Environment = Environment::new(gravity, wind);
+    #[test]
+    fn test_supersampler_grid_offsets() {
+        let sampler: Supersampler =
+            Supersampler::new(2, SamplePattern::Grid, ReconstructionFilter::Box);
+
+        assert_eq!(
+            Supersampler::sample_offsets(&sampler, 3, 4),
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+    }
 
-        let updated_projectile: Projectile = Environment::tick(environment, projectile);
+    #[test]
+    fn test_supersampler_single_sample_hits_pixel_center() {
+        let sampler: Supersampler =
+            Supersampler::new(1, SamplePattern::Grid, ReconstructionFilter::Box);
+        let color: Color = Supersampler::shade_pixel(&sampler, 2, 7, |x, y| Color::new(x, y, 0.0));
 
-        assert_eq!(updated_projectile.position.x, 0.70710677);
-        assert_eq!(updated_projectile.position.y, 1.7071068);
-        assert_eq!(updated_projectile.velocity.x, 0.6971068);
-        assert_eq!(updated_projectile.velocity.y, 0.60710675);
+        assert_eq!(color.red, 2.5);
+        assert_eq!(color.green, 7.5);
     }
 
     #[test]
-    fn test_environment_tick_repeated() {
-        let position: Tuple = Tuple::point(0.0, 1.0, 0.0);
-        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.0, 0.0));
-        let mut projectile: Projectile = Projectile::new(position, velocity);
+    fn test_supersampler_jittered_offsets_are_stratified() {
+        let sampler: Supersampler =
+            Supersampler::new(4, SamplePattern::Jittered, ReconstructionFilter::Box);
+        let offsets: Vec<(f32, f32)> = Supersampler::sample_offsets(&sampler, 5, 9);
+
+        for (index, &(u, v)) in offsets.iter().enumerate() {
+            assert_eq!((u * 4.0) as usize, index % 4);
+            assert_eq!((v * 4.0) as usize, index / 4);
+        }
 
-        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
-        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
-        let environment: Environment = Environment::new(gravity, wind);
+        assert_eq!(offsets, Supersampler::sample_offsets(&sampler, 5, 9));
+        assert_ne!(offsets, Supersampler::sample_offsets(&sampler, 6, 9));
+    }
 
-        while projectile.position.y > 0.0 {
-            projectile = Environment::tick(environment, projectile);
+    #[test]
+    fn test_supersampler_rotated_grid_offsets() {
+        let sampler: Supersampler =
+            Supersampler::new(3, SamplePattern::RotatedGrid, ReconstructionFilter::Box);
+        let offsets: Vec<(f32, f32)> = Supersampler::sample_offsets(&sampler, 0, 0);
+
+        assert_eq!(offsets.len(), 9);
+        for &(u, v) in offsets.iter() {
+            assert!((0.0..1.0).contains(&u));
+            assert!((0.0..1.0).contains(&v));
         }
 
-        assert!(projectile.position.y <= 0.0);
+        // Unlike a regular grid, no two samples share a row or column.
+        for a in 0..offsets.len() {
+            for b in a + 1..offsets.len() {
+                assert!((offsets[a].0 - offsets[b].0).abs() > 0.01);
+                assert!((offsets[a].1 - offsets[b].1).abs() > 0.01);
+            }
+        }
     }
 
     #[test]
-    fn test_color_constructor() {
-        let color: Color = Color::new(-0.5, 0.4, 1.7);
+    fn test_reconstruction_filter_weights() {
+        assert_eq!(
+            ReconstructionFilter::weight_1d(ReconstructionFilter::Box, 0.4),
+            1.0
+        );
+        assert_eq!(
+            ReconstructionFilter::weight_1d(ReconstructionFilter::Box, 0.6),
+            0.0
+        );
+
+        assert_eq!(
+            ReconstructionFilter::weight_1d(ReconstructionFilter::Tent, 0.0),
+            1.0
+        );
+        assert_eq!(
+            ReconstructionFilter::weight_1d(ReconstructionFilter::Tent, -0.5),
+            0.5
+        );
+
+        let gaussian: ReconstructionFilter = ReconstructionFilter::Gaussian;
+        assert!(
+            ReconstructionFilter::weight_1d(gaussian, 0.0)
+                > ReconstructionFilter::weight_1d(gaussian, 1.0)
+        );
+        assert!(float_eq(
+            ReconstructionFilter::weight_1d(gaussian, 1.5),
+            0.0
+        ));
+
+        let mitchell: ReconstructionFilter = ReconstructionFilter::Mitchell;
+        assert!(float_eq(
+            ReconstructionFilter::weight_1d(mitchell, 0.0),
+            8.0 / 9.0
+        ));
+        assert!(ReconstructionFilter::weight_1d(mitchell, 1.5) < 0.0);
+        assert!(float_eq(
+            ReconstructionFilter::weight_1d(mitchell, 2.0),
+            0.0
+        ));
+
+        assert!(float_eq(
+            ReconstructionFilter::weight(ReconstructionFilter::Tent, 0.5, 0.5),
+            0.25
+        ));
+    }
 
-        assert_eq!(color.red, -0.5);
-        assert_eq!(color.green, 0.4);
-        assert_eq!(color.blue, 1.7);
+    #[test]
+    fn test_supersampler_preserves_flat_color() {
+        let filters: [ReconstructionFilter; 4] = [
+            ReconstructionFilter::Box,
+            ReconstructionFilter::Tent,
+            ReconstructionFilter::Gaussian,
+            ReconstructionFilter::Mitchell,
+        ];
+        let patterns: [SamplePattern; 5] = [
+            SamplePattern::Grid,
+            SamplePattern::Jittered,
+            SamplePattern::RotatedGrid,
+            SamplePattern::Halton,
+            SamplePattern::Sobol,
+        ];
+
+        for &filter in filters.iter() {
+            for &pattern in patterns.iter() {
+                let sampler: Supersampler = Supersampler::new(4, pattern, filter);
+                let color: Color =
+                    Supersampler::shade_pixel(&sampler, 1, 1, |_, _| Color::new(0.2, 0.4, 0.6));
+
+                assert!(float_eq(color.red, 0.2));
+                assert!(float_eq(color.green, 0.4));
+                assert!(float_eq(color.blue, 0.6));
+            }
+        }
     }
 
     #[test]
-    fn test_color_to_integers_tuple_min() {
-        let color: Color = Color::new(0.0, 0.0, 0.0);
-        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+    fn test_supersampler_smooths_edges() {
+        // A vertical edge through the middle of pixel column 2.
+        let edge = |x: f32, _: f32| -> Color {
+            if x < 2.5 {
+                Color::new(1.0, 1.0, 1.0)
+            } else {
+                Color::new(0.0, 0.0, 0.0)
+            }
+        };
+
+        let single: Supersampler =
+            Supersampler::new(1, SamplePattern::Grid, ReconstructionFilter::Box);
+        let grid: Supersampler =
+            Supersampler::new(4, SamplePattern::Grid, ReconstructionFilter::Box);
+        let tent: Supersampler =
+            Supersampler::new(4, SamplePattern::Grid, ReconstructionFilter::Tent);
+        let gaussian: Supersampler =
+            Supersampler::new(4, SamplePattern::Grid, ReconstructionFilter::Gaussian);
+
+        assert_eq!(Supersampler::shade_pixel(&single, 2, 0, edge).red, 0.0);
+        assert!(float_eq(
+            Supersampler::shade_pixel(&grid, 2, 0, edge).red,
+            0.5
+        ));
+        assert!(float_eq(
+            Supersampler::shade_pixel(&tent, 2, 0, edge).red,
+            0.5
+        ));
+
+        // The wider Gaussian filter also softens the neighboring pixel.
+        assert_eq!(Supersampler::shade_pixel(&grid, 3, 0, edge).red, 0.0);
+        assert!(Supersampler::shade_pixel(&gaussian, 3, 0, edge).red > 0.0);
+    }
 
-        assert_eq!(red, 0);
-        assert_eq!(green, 0);
-        assert_eq!(blue, 0);
+    #[test]
+    fn test_supersampler_with_renderer() {
+        let sampler: Supersampler =
+            Supersampler::new(3, SamplePattern::Jittered, ReconstructionFilter::Mitchell);
+        let shade = |x: f32, y: f32| -> Color { Color::new((x * y).sin().abs(), 0.0, 0.0) };
+
+        let canvas: Canvas = Renderer::render(&Renderer::with_threads(4), 6, 4, |x, y| {
+            Supersampler::shade_pixel(&sampler, x, y, shade)
+        });
+
+        for (x, y, color) in Canvas::pixels(&canvas) {
+            assert_eq!(
+                color.red,
+                Supersampler::shade_pixel(&sampler, x, y, shade).red
+            );
+        }
     }
 
     #[test]
-    fn test_color_to_integers_tuple_mid() {
-        let color: Color = Color::new(0.5, 0.5, 0.5);
-        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+    fn test_color_contrast() {
+        let colors: [Color; 3] = [
+            Color::new(0.1, 0.5, 0.5),
+            Color::new(0.3, 0.5, 0.5),
+            Color::new(0.2, 0.5, 1.0),
+        ];
+
+        assert!(float_eq(color_contrast(&colors), 0.5));
+        assert_eq!(color_contrast(&colors[0..1]), 0.0);
+    }
 
-        assert_eq!(red, 128);
-        assert_eq!(green, 128);
-        assert_eq!(blue, 128);
+    #[test]
+    fn test_adaptive_sampler_maximum_extra_samples() {
+        assert_eq!(
+            AdaptiveSampler::maximum_extra_samples(AdaptiveSampler::new(0.1, 0)),
+            0
+        );
+        assert_eq!(
+            AdaptiveSampler::maximum_extra_samples(AdaptiveSampler::new(0.1, 1)),
+            5
+        );
+        assert_eq!(
+            AdaptiveSampler::maximum_extra_samples(AdaptiveSampler::new(0.1, 3)),
+            105
+        );
     }
 
     #[test]
-    fn test_color_to_integers_tuple_max() {
-        let color: Color = Color::new(1.0, 1.0, 1.0);
-        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
+    fn test_adaptive_sampler_clamps_max_depth() {
+        let sampler: AdaptiveSampler = AdaptiveSampler::new(0.1, 64);
 
-        assert_eq!(red, 255);
-        assert_eq!(green, 255);
-        assert_eq!(blue, 255);
+        assert_eq!(sampler.max_depth, ADAPTIVE_MAX_DEPTH);
+        assert_eq!(
+            AdaptiveSampler::maximum_extra_samples(sampler),
+            AdaptiveSampler::maximum_extra_samples(AdaptiveSampler::new(0.1, ADAPTIVE_MAX_DEPTH))
+        );
+
+        let output: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(2), 2, 2, |_, _| {
+                Color::new(0.5, 0.5, 0.5)
+            });
+
+        assert_eq!(Canvas::pixel_at(&output.canvas, 1, 1).unwrap().red, 0.5);
     }
 
     #[test]
-    fn test_color_to_integers_tuple_edge() {
-        let color: Color = Color::new(-999.0, 999.0, 999.0);
-        let (red, green, blue): (i32, i32, i32) = Color::to_integers_tuple(&color);
-
-        assert_eq!(red, 0);
-        assert_eq!(green, 255);
-        assert_eq!(blue, 255);
+    fn test_adaptive_sampler_skips_flat_areas() {
+        let calls: AtomicUsize = AtomicUsize::new(0);
+        let sampler: AdaptiveSampler = AdaptiveSampler::new(0.05, 4);
+
+        let output: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(2), 8, 6, |_, _| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Color::new(0.3, 0.6, 0.9)
+            });
+
+        // One sample per corner and nothing more.
+        assert_eq!(calls.load(Ordering::SeqCst), 9 * 7);
+        for (_, _, color) in Canvas::pixels(&output.canvas) {
+            assert!(float_eq(color.red, 0.3));
+            assert!(float_eq(color.blue, 0.9));
+        }
+        for (_, _, color) in Canvas::pixels(&output.debug) {
+            assert_eq!(color.red, 0.0);
+        }
     }
 
     #[test]
-    fn test_color_to_string_min() {
-        let color: Color = Color::new(0.0, 0.0, 0.0);
-        let string: String = Color::to_string(&color);
+    fn test_adaptive_sampler_refines_edges() {
+        // A diagonal edge crossing the canvas.
+        let edge = |x: f32, y: f32| -> Color {
+            if x + y < 5.3 {
+                Color::new(1.0, 1.0, 1.0)
+            } else {
+                Color::new(0.0, 0.0, 0.0)
+            }
+        };
+        let sampler: AdaptiveSampler = AdaptiveSampler::new(0.1, 3);
+        let output: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(3), 6, 6, edge);
+
+        for (x, y, level) in Canvas::pixels(&output.debug) {
+            let diagonal: f32 = (x + y) as f32;
+            let crosses_edge: bool = diagonal < 5.3 && diagonal + 2.0 > 5.3;
+            assert_eq!(level.red > 0.0, crosses_edge);
+        }
 
-        assert_eq!(string, "0 0 0");
+        let inside: Color = Canvas::pixel_at(&output.canvas, 0, 0).unwrap();
+        let on_edge: Color = Canvas::pixel_at(&output.canvas, 2, 2).unwrap();
+        let outside: Color = Canvas::pixel_at(&output.canvas, 5, 5).unwrap();
+
+        assert_eq!(inside.red, 1.0);
+        assert!(on_edge.red > 0.3 && on_edge.red < 1.0);
+        assert_eq!(outside.red, 0.0);
     }
 
     #[test]
-    fn test_color_to_string_max() {
-        let color: Color = Color::new(1.0, 1.0, 1.0);
-        let string: String = Color::to_string(&color);
+    fn test_adaptive_sampler_without_refinement_averages_corners() {
+        let sampler: AdaptiveSampler = AdaptiveSampler::new(0.0, 0);
+        let output: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(1), 2, 1, |x, y| {
+                Color::new(x, y, 0.0)
+            });
 
-        assert_eq!(string, "255 255 255");
+        let pixel: Color = Canvas::pixel_at(&output.canvas, 1, 0).unwrap();
+
+        assert_eq!(pixel.red, 1.5);
+        assert_eq!(pixel.green, 0.5);
     }
 
     #[test]
-    fn test_add_colors() {
-        let color1: Color = Color::new(0.9, 0.6, 0.75);
-        let color2: Color = Color::new(0.7, 0.1, 0.25);
-        let result: Color = color1 + color2;
+    fn test_adaptive_sampler_is_deterministic_across_thread_counts() {
+        let shade = |x: f32, y: f32| -> Color {
+            Color::new(((x * 1.7).sin() * (y * 0.9).cos()).max(0.0), 0.0, 0.0)
+        };
+        let sampler: AdaptiveSampler = AdaptiveSampler::new(0.05, 3);
+
+        let expected: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(1), 12, 9, shade);
+        let result: AdaptiveRender =
+            AdaptiveSampler::render(&sampler, &Renderer::with_threads(5), 12, 9, shade);
+
+        assert_eq!(
+            Canvas::canvas_to_pfm(&result.canvas),
+            Canvas::canvas_to_pfm(&expected.canvas)
+        );
+        assert_eq!(
+            Canvas::canvas_to_pfm(&result.debug),
+            Canvas::canvas_to_pfm(&expected.debug)
+        );
+    }
 
-        assert!(float_eq(result.red, 1.6));
-        assert!(float_eq(result.green, 0.7));
-        assert!(float_eq(result.blue, 1.0));
+    #[test]
+    fn test_color_luminance() {
+        assert!(float_eq(Color::luminance(&Color::new(1.0, 1.0, 1.0)), 1.0));
+        assert!(float_eq(
+            Color::luminance(&Color::new(0.0, 1.0, 0.0)),
+            0.7152
+        ));
+        assert_eq!(Color::luminance(&Color::new(0.0, 0.0, 0.0)), 0.0);
     }
 
     #[test]
-    fn test_sub_colors() {
-        let color1: Color = Color::new(0.9, 0.6, 0.75);
-        let color2: Color = Color::new(0.7, 0.1, 0.25);
-        let result: Color = color1 - color2;
+    fn test_environment_map_direction_to_uv() {
+        let (u, v): (f32, f32) = EnvironmentMap::direction_to_uv(Tuple::vector(0.0, 0.0, -1.0));
+        assert!(float_eq(u, 0.5));
+        assert!(float_eq(v, 0.5));
 
-        assert!(float_eq(result.red, 0.2));
-        assert!(float_eq(result.green, 0.5));
-        assert!(float_eq(result.blue, 0.5));
+        let (u, v): (f32, f32) = EnvironmentMap::direction_to_uv(Tuple::vector(1.0, 0.0, 0.0));
+        assert!(float_eq(u, 0.75));
+        assert!(float_eq(v, 0.5));
+
+        let (_, v): (f32, f32) = EnvironmentMap::direction_to_uv(Tuple::vector(0.0, 2.0, 0.0));
+        assert!(float_eq(v, 0.0));
     }
 
     #[test]
-    fn test_multiply_color_by_scalar() {
-        let color: Color = Color::new(0.9, 0.6, 0.75);
-        let result: Color = color * 2.0;
-
-        assert!(float_eq(result.red, 1.8));
-        assert!(float_eq(result.green, 1.2));
-        assert!(float_eq(result.blue, 1.5));
+    fn test_environment_map_uv_round_trip() {
+        for &(u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)].iter() {
+            let direction: Tuple = EnvironmentMap::uv_to_direction(u, v);
+            let (result_u, result_v): (f32, f32) = EnvironmentMap::direction_to_uv(direction);
+
+            assert!(float_eq(Tuple::magnitude(direction), 1.0));
+            assert!((result_u - u).abs() < 0.0001);
+            assert!((result_v - v).abs() < 0.0001);
+        }
     }
 
     #[test]
-    fn test_multiply_colors() {
-        let color1: Color = Color::new(1.0, 0.2, 0.4);
-        let color2: Color = Color::new(0.9, 1.0, 0.1);
-        let result: Color = color1 * color2;
+    fn test_environment_map_color_at() {
+        let mut canvas: Canvas = Canvas::new(4, 2);
+        Canvas::write_pixel(&mut canvas, 2, 1, Color::new(5.0, 0.0, 0.0));
+        Canvas::write_pixel(&mut canvas, 3, 0, Color::new(0.0, 7.0, 0.0));
+        let map: EnvironmentMap = EnvironmentMap::new(canvas);
 
-        assert!(float_eq(result.red, 0.9));
-        assert!(float_eq(result.green, 0.2));
-        assert!(float_eq(result.blue, 0.04));
+        let below_ahead: Color = EnvironmentMap::color_at(&map, Tuple::vector(0.1, -0.5, -1.0));
+        let above_right: Color = EnvironmentMap::color_at(&map, Tuple::vector(1.0, 0.5, 0.1));
+
+        assert_eq!(below_ahead.red, 5.0);
+        assert_eq!(above_right.green, 7.0);
     }
 
     #[test]
-    fn test_canvas_constructor() {
-        let canvas: Canvas = Canvas::new(255, 240);
+    fn test_environment_map_samples_bright_pixels() {
+        let mut canvas: Canvas = Canvas::new(8, 4);
+        for (_, _, color) in Canvas::pixels_mut(&mut canvas) {
+            *color = Color::new(0.01, 0.01, 0.01);
+        }
+        Canvas::write_pixel(&mut canvas, 5, 1, Color::new(100.0, 100.0, 100.0));
+        let map: EnvironmentMap = EnvironmentMap::new(canvas);
+
+        let mut bright_samples: usize = 0;
+        for i in 0..32 {
+            for j in 0..32 {
+                let u1: f32 = (i as f32 + 0.5) / 32.0;
+                let u2: f32 = (j as f32 + 0.5) / 32.0;
+                let (direction, color, pdf): (Tuple, Color, f32) =
+                    EnvironmentMap::sample(&map, u1, u2);
+
+                assert!(pdf > 0.0);
+                assert!(float_eq(pdf, EnvironmentMap::pdf(&map, direction)));
+                assert_eq!(color.red, EnvironmentMap::color_at(&map, direction).red);
+                if color.red == 100.0 {
+                    bright_samples += 1;
+                }
+            }
+        }
 
-        assert_eq!(canvas.width, 255);
-        assert_eq!(canvas.height, 240);
+        assert!(bright_samples > 32 * 32 * 9 / 10);
+    }
 
-        for pixel in canvas.pixels.iter().flatten() {
-            assert_eq!(pixel.red, 0.0);
-            assert_eq!(pixel.green, 0.0);
-            assert_eq!(pixel.blue, 0.0);
+    #[test]
+    fn test_environment_map_samples_extremely_bright_pixels() {
+        let mut overflowing: Canvas = Canvas::new(8, 4);
+        Canvas::write_pixel(&mut overflowing, 2, 1, Color::new(3.0e38, 3.0e38, 3.0e38));
+        Canvas::write_pixel(&mut overflowing, 5, 2, Color::new(3.0e38, 3.0e38, 3.0e38));
+
+        let mut infinite: Canvas = Canvas::new(8, 4);
+        Canvas::write_pixel(&mut infinite, 1, 1, Color::new(1.0, 1.0, 1.0));
+        Canvas::write_pixel(&mut infinite, 5, 1, Color::new(f32::INFINITY, 0.0, 0.0));
+        Canvas::write_pixel(&mut infinite, 6, 2, Color::new(f32::NAN, 1.0, 1.0));
+
+        for canvas in [overflowing, infinite] {
+            let map: EnvironmentMap = EnvironmentMap::new(canvas);
+
+            for i in 0..8 {
+                for j in 0..8 {
+                    let u1: f32 = (i as f32 + 0.5) / 8.0;
+                    let u2: f32 = (j as f32 + 0.5) / 8.0;
+                    let (direction, _, pdf): (Tuple, Color, f32) =
+                        EnvironmentMap::sample(&map, u1, u2);
+
+                    assert!(direction.x.is_finite());
+                    assert!(direction.y.is_finite());
+                    assert!(direction.z.is_finite());
+                    assert!(pdf.is_finite() && pdf > 0.0);
+                }
+            }
         }
     }
 
     #[test]
-    fn test_get_pixel_from_canvas() {
-        let canvas: Canvas = Canvas::new(10, 20);
-        let pixel: Color = Canvas::pixel_at(canvas, 2, 3);
+    fn test_environment_map_pdf_integrates_to_one() {
+        let mut canvas: Canvas = Canvas::new(16, 8);
+        for (x, y, color) in Canvas::pixels_mut(&mut canvas) {
+            *color = Color::new((x * y) as f32, x as f32 * 0.1, 1.0);
+        }
+        let map: EnvironmentMap = EnvironmentMap::new(canvas);
+
+        let steps: usize = 400;
+        let mut total: f32 = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u: f32 = (i as f32 + 0.5) / steps as f32;
+                let v: f32 = (j as f32 + 0.5) / steps as f32;
+                let direction: Tuple = EnvironmentMap::uv_to_direction(u, v);
+                let solid_angle: f32 =
+                    (2.0 * PI / steps as f32) * (PI / steps as f32) * (v * PI).sin();
+
+                total += EnvironmentMap::pdf(&map, direction) * solid_angle;
+            }
+        }
 
-        assert_eq!(pixel.red, 0.0);
-        assert_eq!(pixel.green, 0.0);
-        assert_eq!(pixel.blue, 0.0);
+        assert!((total - 1.0).abs() < 0.01);
     }
 
     #[test]
-    fn test_write_pixel_to_canvas() {
-        let canvas: Canvas = Canvas::new(10, 20);
-        let color: Color = Color::new(1.0, 0.0, 0.0);
-        let updated_canvas: Canvas = Canvas::write_pixel(canvas, 2, 3, color);
-        let pixel: Color = Canvas::pixel_at(updated_canvas, 2, 3);
+    fn test_environment_map_uniform_pdf() {
+        let map: EnvironmentMap = EnvironmentMap::new(Canvas::new(8, 4));
+        let pdf: f32 = EnvironmentMap::pdf(&map, Tuple::vector(0.3, 0.2, -1.0));
 
-        assert_eq!(pixel.red, 1.0);
-        assert_eq!(pixel.green, 0.0);
-        assert_eq!(pixel.blue, 0.0);
+        // A black map samples the sphere roughly uniformly.
+        assert!((pdf * 4.0 * PI - 1.0).abs() < 0.2);
     }
 
     #[test]
-    fn test_canvas_to_ppm() {
-        let mut canvas: Canvas = Canvas::new(5, 3);
+    fn test_sample_cdf() {
+        let cdf: Vec<f64> = vec![0.0, 1.0, 1.0, 4.0];
 
-        let color1: Color = Color::new(1.0, 0.0, 0.0);
-        let color2: Color = Color::new(0.0, 0.5, 0.0);
-        let color3: Color = Color::new(0.0, 0.0, 1.0);
+        assert_eq!(sample_cdf(&cdf, 0.0), 0);
+        assert_eq!(sample_cdf(&cdf, 0.2), 0);
+        assert_eq!(sample_cdf(&cdf, 0.25), 2);
+        assert_eq!(sample_cdf(&cdf, 0.99), 2);
+    }
 
-        canvas = Canvas::write_pixel(canvas, 0, 0, color1);
-        canvas = Canvas::write_pixel(canvas, 2, 1, color2);
-        canvas = Canvas::write_pixel(canvas, 4, 2, color3);
+    #[test]
+    fn test_environment_map_read_from_file() {
+        let mut canvas: Canvas = Canvas::new(4, 2);
+        Canvas::write_pixel(&mut canvas, 2, 1, Color::new(3.0, 0.0, 0.0));
+        let path: &Path = Path::new("./assets/environment.pfm");
+        fs::write(path, Canvas::canvas_to_pfm(&canvas)).expect("Unable to write file");
 
-        let ppm: String = Canvas::canvas_to_ppm(canvas);
+        let map: Result<EnvironmentMap, String> = EnvironmentMap::read_from_file(path);
 
-        let expected_output: String = "P3
-5 3
-255
-255 0 0 0 0 0 0 0 0 0 0 0 0 0 0
-0 0 0 0 0 0 0 128 0 0 0 0 0 0 0
-0 0 0 0 0 0 0 0 0 0 0 0 0 0 255
-"
-        .to_string();
+        // Clean up
+        fs::remove_file(path).expect("Failed to remove pfm file.");
 
-        assert_eq!(ppm, expected_output);
+        let map: EnvironmentMap = map.expect("Failed to read environment map.");
+        let color: Color = EnvironmentMap::color_at(&map, Tuple::vector(0.0, -0.5, -1.0));
+        assert_eq!(color.red, 3.0);
+        assert!(EnvironmentMap::read_from_file(Path::new("./assets/environment.png")).is_err());
     }
 
     #[test]
-    fn test_write_ppm_to_file() {
-        let mut canvas: Canvas = Canvas::new(5, 3);
+    fn test_environment_map_read_empty_file() {
+        let path: &Path = Path::new("./assets/empty_environment.hdr");
+        fs::write(path, Canvas::canvas_to_hdr(&Canvas::new(0, 0))).expect("Unable to write file");
 
-        let color1: Color = Color::new(1.0, 0.0, 0.0);
-        let color2: Color = Color::new(0.0, 0.5, 0.0);
-        let color3: Color = Color::new(0.0, 0.0, 1.0);
+        let map: Result<EnvironmentMap, String> = EnvironmentMap::read_from_file(path);
 
-        canvas = Canvas::write_pixel(canvas, 0, 0, color1);
-        canvas = Canvas::write_pixel(canvas, 2, 1, color2);
-        canvas = Canvas::write_pixel(canvas, 4, 2, color3);
+        // Clean up
+        fs::remove_file(path).expect("Failed to remove hdr file.");
 
-        let ppm: String = Canvas::canvas_to_ppm(canvas);
+        assert!(map.is_err());
+    }
 
-        Canvas::write_ppm_to_file(ppm);
+    #[test]
+    #[should_panic(expected = "must not be empty")]
+    fn test_environment_map_rejects_empty_canvas() {
+        EnvironmentMap::new(Canvas::new(0, 3));
+    }
 
-        let file_exists: bool = Path::new("./assets/canvas.ppm").exists();
-        assert!(file_exists);
+    #[test]
+    fn test_supersampler_low_discrepancy_offsets() {
+        for &pattern in [SamplePattern::Halton, SamplePattern::Sobol].iter() {
+            let sampler: Supersampler = Supersampler::new(4, pattern, ReconstructionFilter::Box);
+            let offsets: Vec<(f32, f32)> = Supersampler::sample_offsets(&sampler, 2, 3);
+
+            assert_eq!(offsets.len(), 16);
+            for &(u, v) in offsets.iter() {
+                assert!((0.0..1.0).contains(&u));
+                assert!((0.0..1.0).contains(&v));
+            }
 
-        // Clean up
-        fs::remove_file("./assets/canvas.ppm").expect("Failed to remove ppm file.");
+            assert_eq!(offsets, Supersampler::sample_offsets(&sampler, 2, 3));
+            assert_ne!(offsets, Supersampler::sample_offsets(&sampler, 3, 3));
+        }
     }
 
     #[test]
-    fn test_write_projectile_ppm_to_file() {
-        let canvas_width: usize = 900;
-        let canvas_height: usize = 550;
-        let mut canvas: Canvas = Canvas::new(canvas_width, canvas_height);
+    fn test_supersampler_seed() {
+        let sampler: Supersampler =
+            Supersampler::new(2, SamplePattern::Jittered, ReconstructionFilter::Box);
+        let reseeded: Supersampler = Supersampler::with_seed(sampler, 7);
+
+        assert_eq!(reseeded.seed, 7);
+        assert_eq!(reseeded.samples_per_axis, 2);
+        assert_eq!(
+            Supersampler::sample_offsets(&Supersampler::with_seed(sampler, 7), 1, 1),
+            Supersampler::sample_offsets(&reseeded, 1, 1)
+        );
+        assert_ne!(
+            Supersampler::sample_offsets(&sampler, 1, 1),
+            Supersampler::sample_offsets(&reseeded, 1, 1)
+        );
+    }
 
-        let starting_position: Tuple = Tuple::point(0.0, 1.0, 0.0);
-        let velocity: Tuple = Tuple::normalize(Tuple::vector(1.0, 1.8, 0.0)) * 11.25;
-        let mut projectile: Projectile = Projectile::new(starting_position, velocity);
+    #[test]
+    fn test_seeded_render_is_bit_identical_across_thread_counts() {
+        let shade = |x: f32, y: f32| -> Color {
+            Color::new(
+                (x * 0.37).sin().abs(),
+                (y * 0.21).cos().abs(),
+                (x * y).fract(),
+            )
+        };
+        let render = |threads: usize, seed: u64| -> Vec<u8> {
+            let sampler: Supersampler = Supersampler::with_seed(
+                Supersampler::new(3, SamplePattern::Jittered, ReconstructionFilter::Gaussian),
+                seed,
+            );
+            let canvas: Canvas =
+                Renderer::render(&Renderer::with_threads(threads), 16, 9, |x, y| {
+                    Supersampler::shade_pixel(&sampler, x, y, shade)
+                });
+            Canvas::canvas_to_pfm(&canvas)
+        };
+
+        let expected: Vec<u8> = render(1, 1234);
+
+        assert_eq!(render(3, 1234), expected);
+        assert_eq!(render(16, 1234), expected);
+        assert_ne!(render(3, 4321), expected);
+    }
 
-        let gravity: Tuple = Tuple::vector(0.0, -0.1, 0.0);
-        let wind: Tuple = Tuple::vector(-0.01, 0.0, 0.0);
-        let environment: Environment = Environment::new(gravity, wind);
-        let color: Color = Color::new(0.5, 0.0, 0.5);
+    #[test]
+    fn test_rng_matches_pcg32_reference() {
+        // First outputs of the reference pcg32-demo seeded with (42, 54).
+        let mut rng: Rng = Rng::new(42, 54);
+        let expected: [u32; 6] = [
+            0xa15c_02b7,
+            0x7b47_f409,
+            0xba1d_3330,
+            0x83d2_f293,
+            0xbfa4_784b,
+            0xcbed_606e,
+        ];
+
+        for &value in expected.iter() {
+            assert_eq!(Rng::next_u32(&mut rng), value);
+        }
+    }
 
-        while projectile.position.y > 0.0 {
-            projectile = Environment::tick(environment, projectile);
-            canvas = Canvas::write_pixel(
-                canvas,
-                projectile.position.x as usize,
-                canvas_height - (projectile.position.y as usize),
-                color,
-            );
+    #[test]
+    fn test_rng_next_f32_range() {
+        let mut rng: Rng = Rng::new(1, 1);
+        let mut total: f32 = 0.0;
+
+        for _ in 0..10_000 {
+            let value: f32 = Rng::next_f32(&mut rng);
+            assert!((0.0..1.0).contains(&value));
+            total += value;
         }
 
-        let ppm: String = Canvas::canvas_to_ppm(canvas);
+        assert!((total / 10_000.0 - 0.5).abs() < 0.01);
+    }
 
-        Canvas::write_ppm_to_file(ppm);
+    #[test]
+    fn test_rng_for_pixel() {
+        let rng: Rng = Rng::for_pixel(9, 3, 4, 5);
 
-        let file_exists: bool = Path::new("./assets/canvas.ppm").exists();
-        assert!(file_exists);
+        assert_eq!(rng, Rng::for_pixel(9, 3, 4, 5));
+        assert_ne!(rng, Rng::for_pixel(10, 3, 4, 5));
+        assert_ne!(rng, Rng::for_pixel(9, 4, 3, 5));
+        assert_ne!(rng, Rng::for_pixel(9, 3, 4, 6));
+    }
 
-        // Clean up
-        fs::remove_file("./assets/canvas.ppm").expect("Failed to remove ppm file.");
+    #[test]
+    fn test_halton() {
+        let base_two: Vec<f32> = (0..5).map(|index| halton(index, 0)).collect();
+        let base_three: Vec<f32> = (0..5).map(|index| halton(index, 1)).collect();
+
+        assert_eq!(base_two, vec![0.0, 0.5, 0.25, 0.75, 0.125]);
+        for (value, expected) in base_three
+            .iter()
+            .zip([0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0].iter())
+        {
+            assert!(float_eq(*value, *expected));
+        }
+    }
+
+    #[test]
+    fn test_sobol() {
+        let first: Vec<f32> = (0..5).map(|index| sobol(index, 0)).collect();
+        let second: Vec<f32> = (0..5).map(|index| sobol(index, 1)).collect();
+
+        assert_eq!(first, vec![0.0, 0.5, 0.25, 0.75, 0.125]);
+        assert_eq!(second, vec![0.0, 0.5, 0.75, 0.25, 0.625]);
+    }
+
+    #[test]
+    fn test_sobol_is_stratified() {
+        // Every Sobol dimension puts one of the first 2^k points in each
+        // interval of width 2^-k.
+        for dimension in 0..6 {
+            let mut cells: Vec<bool> = vec![false; 64];
+            for index in 0..64 {
+                let cell: usize = (sobol(index, dimension) * 64.0) as usize;
+                assert!(!cells[cell]);
+                cells[cell] = true;
+            }
+        }
     }
 
     #[test]
@@ -892,4 +4303,108 @@ mod tests {
     fn float_eq(a: f32, b: f32) -> bool {
         (a - b).abs() < EPSILON
     }
+
+    fn png_chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut chunks: Vec<(String, Vec<u8>)> = vec![];
+        let mut position: usize = 8;
+
+        while position < png.len() {
+            let mut length_bytes: [u8; 4] = [0; 4];
+            length_bytes.copy_from_slice(&png[position..position + 4]);
+            let length: usize = u32::from_be_bytes(length_bytes) as usize;
+
+            let chunk: &[u8] = &png[position + 4..position + 8 + length];
+            let mut checksum: [u8; 4] = [0; 4];
+            checksum.copy_from_slice(&png[position + 8 + length..position + 12 + length]);
+            assert_eq!(crc32(chunk), u32::from_be_bytes(checksum));
+
+            chunks.push((
+                String::from_utf8(chunk[0..4].to_vec()).unwrap(),
+                chunk[4..].to_vec(),
+            ));
+            position += 12 + length;
+        }
+
+        chunks
+    }
+
+    // Decodes the stored and fixed Huffman deflate blocks produced by
+    // zlib_compress.
+    fn inflate(deflate: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = vec![];
+        let mut bit_position: usize = 0;
+
+        let read_bits = |count: usize, position: &mut usize| -> usize {
+            let mut value: usize = 0;
+            for i in 0..count {
+                let bit: u8 = (deflate[*position / 8] >> (*position % 8)) & 1;
+                value |= (bit as usize) << i;
+                *position += 1;
+            }
+            value
+        };
+
+        loop {
+            let is_final: usize = read_bits(1, &mut bit_position);
+            let block_type: usize = read_bits(2, &mut bit_position);
+
+            if block_type == 0 {
+                bit_position = bit_position.div_ceil(8) * 8;
+                let length: usize = read_bits(16, &mut bit_position);
+                let inverted_length: usize = read_bits(16, &mut bit_position);
+                assert_eq!(length ^ 0xffff, inverted_length);
+                for _ in 0..length {
+                    output.push(read_bits(8, &mut bit_position) as u8);
+                }
+            } else {
+                assert_eq!(block_type, 1);
+                loop {
+                    let mut code: usize = 0;
+                    let mut length: usize = 0;
+                    let symbol: usize = loop {
+                        code = (code << 1) | read_bits(1, &mut bit_position);
+                        length += 1;
+                        match (length, code) {
+                            (7, 0..=0x17) => break code + 256,
+                            (8, 0x30..=0xbf) => break code - 0x30,
+                            (8, 0xc0..=0xc7) => break code - 0xc0 + 280,
+                            (9, 0x190..=0x1ff) => break code - 0x190 + 144,
+                            _ => assert!(length < 9),
+                        }
+                    };
+
+                    if symbol < 256 {
+                        output.push(symbol as u8);
+                    } else if symbol == 256 {
+                        break;
+                    } else {
+                        let index: usize = symbol - 257;
+                        let length: usize = DEFLATE_LENGTH_BASE[index] as usize
+                            + read_bits(
+                                DEFLATE_LENGTH_EXTRA_BITS[index] as usize,
+                                &mut bit_position,
+                            );
+
+                        let mut distance_code: usize = 0;
+                        for _ in 0..5 {
+                            distance_code = (distance_code << 1) | read_bits(1, &mut bit_position);
+                        }
+                        let distance: usize = DEFLATE_DISTANCE_BASE[distance_code] as usize
+                            + read_bits(
+                                DEFLATE_DISTANCE_EXTRA_BITS[distance_code] as usize,
+                                &mut bit_position,
+                            );
+
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+
+            if is_final == 1 {
+                return output;
+            }
+        }
+    }
 }
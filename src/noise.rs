@@ -0,0 +1,394 @@
+use crate::{splitmix64, Rng, Tuple};
+
+// NOISE
+
+// Gradient noise is built from a shuffled permutation of 0..256, doubled so
+// lookups can add lattice offsets without wrapping. Shuffling with a seeded
+// Rng makes every function below deterministic for a given seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Noise {
+    seed: u64,
+    permutation: Vec<usize>,
+}
+
+// The twelve edge midpoints of a cube, used as gradients by simplex noise.
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut rng: Rng = Rng::new(seed, 0);
+        let mut permutation: Vec<usize> = (0..256).collect();
+
+        for i in (1..256).rev() {
+            let j: usize = Rng::next_u32(&mut rng) as usize % (i + 1);
+            permutation.swap(i, j);
+        }
+
+        let doubled: Vec<usize> = permutation
+            .iter()
+            .chain(permutation.iter())
+            .copied()
+            .collect();
+
+        Noise {
+            seed,
+            permutation: doubled,
+        }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> usize {
+        let p: &[usize] = &self.permutation;
+
+        p[p[p[(x & 255) as usize] + (y & 255) as usize] + (z & 255) as usize]
+    }
+
+    // Ken Perlin's improved noise. Zero at every integer lattice point and
+    // roughly within -1..1 elsewhere.
+    pub fn perlin(&self, point: Tuple) -> f32 {
+        let (x0, y0, z0): (f32, f32, f32) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let (x, y, z): (f32, f32, f32) = (point.x - x0, point.y - y0, point.z - z0);
+        let (xi, yi, zi): (i32, i32, i32) = (x0 as i32, y0 as i32, z0 as i32);
+        let (u, v, w): (f32, f32, f32) = (fade(x), fade(y), fade(z));
+
+        let corner = |dx: i32, dy: i32, dz: i32| -> f32 {
+            let hash: usize = self.hash(xi + dx, yi + dy, zi + dz);
+            perlin_gradient(hash, x - dx as f32, y - dy as f32, z - dz as f32)
+        };
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+                lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+            ),
+            lerp(
+                v,
+                lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+                lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+            ),
+        )
+    }
+
+    // Stefan Gustavson's formulation of Ken Perlin's simplex noise, scaled to
+    // roughly -1..1. Cheaper than Perlin noise and without its axis-aligned
+    // artifacts.
+    pub fn simplex(&self, point: Tuple) -> f32 {
+        const SKEW: f32 = 1.0 / 3.0;
+        const UNSKEW: f32 = 1.0 / 6.0;
+
+        // Find the simplex cell containing the point.
+        let skew: f32 = (point.x + point.y + point.z) * SKEW;
+        let i: f32 = (point.x + skew).floor();
+        let j: f32 = (point.y + skew).floor();
+        let k: f32 = (point.z + skew).floor();
+        let unskew: f32 = (i + j + k) * UNSKEW;
+        let x0: f32 = point.x - (i - unskew);
+        let y0: f32 = point.y - (j - unskew);
+        let z0: f32 = point.z - (k - unskew);
+
+        // Pick which of the six tetrahedra in the cube the point is in.
+        let (first, second): ([i32; 3], [i32; 3]) = if x0 >= y0 {
+            if y0 >= z0 {
+                ([1, 0, 0], [1, 1, 0])
+            } else if x0 >= z0 {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if y0 < z0 {
+            ([0, 0, 1], [0, 1, 1])
+        } else if x0 < z0 {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+
+        let offsets: [[i32; 3]; 4] = [[0, 0, 0], first, second, [1, 1, 1]];
+        let (ii, jj, kk): (i32, i32, i32) = (i as i32, j as i32, k as i32);
+        let mut total: f32 = 0.0;
+
+        for (corner, offset) in offsets.iter().enumerate() {
+            let x: f32 = x0 - offset[0] as f32 + corner as f32 * UNSKEW;
+            let y: f32 = y0 - offset[1] as f32 + corner as f32 * UNSKEW;
+            let z: f32 = z0 - offset[2] as f32 + corner as f32 * UNSKEW;
+            let falloff: f32 = 0.6 - x * x - y * y - z * z;
+
+            if falloff > 0.0 {
+                let hash: usize = self.hash(ii + offset[0], jj + offset[1], kk + offset[2]);
+                let gradient: [f32; 3] = GRADIENTS[hash % 12];
+                let dot: f32 = gradient[0] * x + gradient[1] * y + gradient[2] * z;
+                total += falloff.powi(4) * dot;
+            }
+        }
+
+        32.0 * total
+    }
+
+    // Fractal Brownian motion: octaves of Perlin noise at rising frequency
+    // and falling amplitude, normalized back to roughly -1..1.
+    pub fn fbm(&self, point: Tuple, octaves: usize, lacunarity: f32, gain: f32) -> f32 {
+        let mut total: f32 = 0.0;
+        let mut amplitude: f32 = 1.0;
+        let mut frequency: f32 = 1.0;
+        let mut maximum: f32 = 0.0;
+
+        for _ in 0..octaves {
+            total += amplitude * self.perlin(scale_point(point, frequency));
+            maximum += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+
+        if maximum > 0.0 {
+            total / maximum
+        } else {
+            0.0
+        }
+    }
+
+    // Like fbm but summing absolute values, which gives the creased look
+    // used for marble veins and fire. Always between 0 and roughly 1.
+    pub fn turbulence(&self, point: Tuple, octaves: usize) -> f32 {
+        let mut total: f32 = 0.0;
+        let mut amplitude: f32 = 1.0;
+        let mut frequency: f32 = 1.0;
+        let mut maximum: f32 = 0.0;
+
+        for _ in 0..octaves {
+            total += amplitude * self.perlin(scale_point(point, frequency)).abs();
+            maximum += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        if maximum > 0.0 {
+            total / maximum
+        } else {
+            0.0
+        }
+    }
+
+    // Steven Worley's cellular noise. Each unit cell holds one feature point,
+    // and the result is the distance to the nearest and second nearest
+    // feature points. The nearest distance alone gives cells. The difference
+    // between the two gives cracks between stones.
+    pub fn worley(&self, point: Tuple) -> (f32, f32) {
+        let (cell_x, cell_y, cell_z): (i32, i32, i32) = (
+            point.x.floor() as i32,
+            point.y.floor() as i32,
+            point.z.floor() as i32,
+        );
+        let mut nearest: f32 = f32::MAX;
+        let mut second_nearest: f32 = f32::MAX;
+
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (x, y, z): (i32, i32, i32) = (cell_x + dx, cell_y + dy, cell_z + dz);
+                    let feature: Tuple = self.feature_point(x, y, z);
+                    let distance: f32 = Tuple::magnitude(point - feature);
+
+                    if distance < nearest {
+                        second_nearest = nearest;
+                        nearest = distance;
+                    } else if distance < second_nearest {
+                        second_nearest = distance;
+                    }
+                }
+            }
+        }
+
+        (nearest, second_nearest)
+    }
+
+    fn feature_point(&self, x: i32, y: i32, z: i32) -> Tuple {
+        let cell: u64 = ((x as u32 as u64) << 42) ^ ((y as u32 as u64) << 21) ^ z as u32 as u64;
+        let mut rng: Rng = Rng::new(splitmix64(self.seed ^ splitmix64(cell)), 1);
+
+        Tuple::point(
+            x as f32 + Rng::next_f32(&mut rng),
+            y as f32 + Rng::next_f32(&mut rng),
+            z as f32 + Rng::next_f32(&mut rng),
+        )
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// Picks one of twelve cube edge directions from the low four bits of the
+// hash, repeating four of them to fill sixteen slots.
+fn perlin_gradient(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    let h: usize = hash & 15;
+    let u: f32 = if h < 8 { x } else { y };
+    let v: f32 = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn scale_point(point: Tuple, scale: f32) -> Tuple {
+    Tuple::point(point.x * scale, point.y * scale, point.z * scale)
+}
+
+// TESTS
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_deterministic_for_a_seed() {
+        let noise1: Noise = Noise::new(42);
+        let noise2: Noise = Noise::new(42);
+        let noise3: Noise = Noise::new(43);
+        let point: Tuple = Tuple::point(1.3, -2.7, 0.45);
+
+        assert_eq!(noise1, noise2);
+        assert_eq!(Noise::perlin(&noise1, point), Noise::perlin(&noise2, point));
+        assert_eq!(
+            Noise::simplex(&noise1, point),
+            Noise::simplex(&noise2, point)
+        );
+        assert_eq!(Noise::worley(&noise1, point), Noise::worley(&noise2, point));
+        assert_ne!(Noise::perlin(&noise1, point), Noise::perlin(&noise3, point));
+        assert_ne!(
+            Noise::simplex(&noise1, point),
+            Noise::simplex(&noise3, point)
+        );
+        assert_ne!(Noise::worley(&noise1, point), Noise::worley(&noise3, point));
+    }
+
+    #[test]
+    fn test_noise_permutation() {
+        let noise: Noise = Noise::new(7);
+        let mut values: Vec<usize> = noise.permutation[..256].to_vec();
+        values.sort_unstable();
+
+        assert_eq!(noise.permutation.len(), 512);
+        assert_eq!(&noise.permutation[..256], &noise.permutation[256..]);
+        assert_eq!(values, (0..256).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_perlin_is_zero_on_lattice_points() {
+        let noise: Noise = Noise::new(1);
+
+        for &(x, y, z) in [(0.0, 0.0, 0.0), (3.0, -2.0, 5.0), (-7.0, 11.0, -1.0)].iter() {
+            assert_eq!(Noise::perlin(&noise, Tuple::point(x, y, z)), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_noise_ranges() {
+        let noise: Noise = Noise::new(3);
+        let mut perlin_spread: f32 = 0.0;
+        let mut simplex_spread: f32 = 0.0;
+
+        for i in 0..2000 {
+            let t: f32 = i as f32 * 0.137;
+            let point: Tuple = Tuple::point(t.sin() * 9.0, t * 0.31, (t * 0.7).cos() * 5.0);
+
+            let perlin: f32 = Noise::perlin(&noise, point);
+            let simplex: f32 = Noise::simplex(&noise, point);
+            let fbm: f32 = Noise::fbm(&noise, point, 5, 2.0, 0.5);
+            let turbulence: f32 = Noise::turbulence(&noise, point, 5);
+
+            assert!((-1.1..=1.1).contains(&perlin));
+            assert!((-1.1..=1.1).contains(&simplex));
+            assert!((-1.1..=1.1).contains(&fbm));
+            assert!((0.0..=1.1).contains(&turbulence));
+
+            perlin_spread = perlin_spread.max(perlin.abs());
+            simplex_spread = simplex_spread.max(simplex.abs());
+        }
+
+        assert!(perlin_spread > 0.4);
+        assert!(simplex_spread > 0.4);
+    }
+
+    #[test]
+    fn test_noise_is_continuous() {
+        let noise: Noise = Noise::new(5);
+        let step: Tuple = Tuple::vector(0.001, 0.001, 0.001);
+
+        for i in 0..200 {
+            let point: Tuple = Tuple::point(i as f32 * 0.173, i as f32 * -0.091, 0.5);
+
+            assert!(
+                (Noise::perlin(&noise, point) - Noise::perlin(&noise, point + step)).abs() < 0.02
+            );
+            assert!(
+                (Noise::simplex(&noise, point) - Noise::simplex(&noise, point + step)).abs() < 0.05
+            );
+        }
+    }
+
+    #[test]
+    fn test_fbm_with_one_octave_is_perlin() {
+        let noise: Noise = Noise::new(9);
+        let point: Tuple = Tuple::point(0.3, 1.7, -2.2);
+
+        assert_eq!(
+            Noise::fbm(&noise, point, 1, 2.0, 0.5),
+            Noise::perlin(&noise, point)
+        );
+        assert_eq!(Noise::fbm(&noise, point, 0, 2.0, 0.5), 0.0);
+        assert_eq!(
+            Noise::turbulence(&noise, point, 1),
+            Noise::perlin(&noise, point).abs()
+        );
+    }
+
+    #[test]
+    fn test_worley() {
+        let noise: Noise = Noise::new(11);
+
+        for i in 0..500 {
+            let t: f32 = i as f32 * 0.29;
+            let point: Tuple = Tuple::point(t, (t * 1.3).sin() * 4.0, -t * 0.5);
+            let (nearest, second_nearest): (f32, f32) = Noise::worley(&noise, point);
+
+            // The point's own cell always holds a feature point.
+            assert!(nearest >= 0.0);
+            assert!(nearest <= 3.0_f32.sqrt());
+            assert!(nearest <= second_nearest);
+        }
+    }
+
+    #[test]
+    fn test_worley_is_zero_at_feature_points() {
+        let noise: Noise = Noise::new(13);
+        let feature: Tuple = Noise::feature_point(&noise, 2, -3, 4);
+        let (nearest, _): (f32, f32) = Noise::worley(&noise, feature);
+
+        assert_eq!(nearest, 0.0);
+        assert!(feature.x >= 2.0 && feature.x < 3.0);
+        assert!(feature.y >= -3.0 && feature.y < -2.0);
+        assert!(feature.z >= 4.0 && feature.z < 5.0);
+    }
+}